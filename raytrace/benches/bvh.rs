@@ -0,0 +1,48 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::prelude::*;
+use std::sync::Arc;
+
+use raytrace::{
+    vec3::{Vec3},
+    ray::{Ray},
+    hitable::{Hitable, Sphere, World},
+    material::{Material, Lambertian},
+};
+
+const NUM_RAYS: usize = 1000;
+
+fn random_vec3(rng: &mut StdRng) -> Vec3 {
+    Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()) * 2.0 - Vec3::ones()
+}
+
+fn random_world(num_spheres: usize) -> World {
+    let mut rng = StdRng::seed_from_u64(num_spheres as u64);
+    let material: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    let objects: Vec<Box<dyn Hitable>> = (0..num_spheres)
+        .map(|_| Box::new(Sphere::new(random_vec3(&mut rng) * 50.0, 0.5, material.clone())) as Box<dyn Hitable>)
+        .collect();
+    World::new(objects)
+}
+
+fn world_hit(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let rays: Vec<Ray> = (0..NUM_RAYS)
+        .map(|_| Ray::new(random_vec3(&mut rng) * 50.0, random_vec3(&mut rng), 0.0))
+        .collect();
+
+    let mut group = c.benchmark_group("world_hit");
+    for &num_spheres in &[100, 1000, 10000] {
+        let linear = random_world(num_spheres);
+        let mut bvh = random_world(num_spheres);
+        bvh.build_bvh();
+        for (name, world) in &[("linear", &linear), ("bvh", &bvh)] {
+            group.bench_with_input(BenchmarkId::new(*name, num_spheres), world, |b, world| {
+                b.iter(|| rays.iter().filter(|&&ray| world.hit(ray, 0.0001, f32::MAX).is_some()).count())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, world_hit);
+criterion_main!(benches);
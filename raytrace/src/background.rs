@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use crate::{
+    vec3::{Vec3},
+    ray::{Ray},
+    environment::{EnvironmentMap},
+};
+
+// radiance for rays that escape the scene
+#[derive(Clone)]
+pub enum Background {
+    Gradient { bottom: Vec3, top: Vec3 },
+    Solid(Vec3),
+    // also sampled as a light, see `Light::Environment`
+    Environment(Arc<EnvironmentMap>),
+}
+
+impl Background {
+    pub fn sky() -> Background {
+        Background::Gradient { bottom: Vec3::ones(), top: Vec3::new(0.5, 0.7, 1.0) }
+    }
+
+    pub fn color(&self, ray: Ray) -> Vec3 {
+        match self {
+            Background::Gradient { bottom, top } => {
+                let unit_direction = ray.direction.make_unit_vector();
+                let t = 0.5 * (unit_direction.y + 1.0);
+                *bottom * (1.0 - t) + *top * t
+            }
+            Background::Solid(color) => *color,
+            Background::Environment(map) => map.radiance(ray.direction),
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{
+    vec3::{Vec3},
+    ray::{Ray},
+    aabb::{Aabb},
+    hitable::{HitRecord, Hitable},
+};
+
+const NUM_BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+// beyond this depth nodes are split at the median to bound the traversal stack
+const MAX_SAH_DEPTH: usize = 64;
+const STACK_SIZE: usize = 128;
+// cost of a traversal step relative to a primitive intersection
+const TRAVERSAL_COST: f32 = 0.125;
+
+#[derive(Copy, Clone)]
+struct Node {
+    bbox: Aabb,
+    // leaves cover objects[start..start + count], interior nodes have count 0, their
+    // first child directly follows them and `start` is the index of the second child
+    start: usize,
+    count: usize,
+    axis: usize,
+}
+
+#[derive(Copy, Clone)]
+struct Primitive {
+    index: usize,
+    bbox: Aabb,
+    centroid: Vec3,
+}
+
+// bounding volume hierarchy over a slice of bounded objects, split with a binned
+// surface area heuristic
+pub struct Bvh {
+    nodes: Vec<Node>,
+}
+
+impl Bvh {
+    // builds the hierarchy over the primitives' bounding boxes and returns the order
+    // the primitives must be stored in, so that every leaf covers a contiguous range
+    pub fn new(boxes: &[Aabb]) -> (Bvh, Vec<usize>) {
+        let mut prims: Vec<Primitive> = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, &bbox)| Primitive { index, bbox, centroid: bbox.centroid() })
+            .collect();
+        let mut bvh = Bvh { nodes: Vec::with_capacity(2 * prims.len()) };
+        if !prims.is_empty() {
+            bvh.build_node(&mut prims, 0, 0);
+        }
+        (bvh, prims.iter().map(|p| p.index).collect())
+    }
+
+    // reorders `objects` to match the hierarchy, panics if an object has no bounding box
+    pub fn build(objects: &mut Vec<Box<dyn Hitable>>) -> Bvh {
+        let boxes: Vec<Aabb> = objects
+            .iter()
+            .map(|o| o.bounding_box().expect("bvh objects must be bounded"))
+            .collect();
+        let (bvh, order) = Bvh::new(&boxes);
+        let mut taken: Vec<Option<Box<dyn Hitable>>> = objects.drain(..).map(Some).collect();
+        objects.extend(order.iter().map(|&i| taken[i].take().unwrap()));
+        bvh
+    }
+
+    fn build_node(&mut self, prims: &mut [Primitive], start: usize, depth: usize) -> usize {
+        let bbox = prims[1..].iter().fold(prims[0].bbox, |b, p| b.surrounding(p.bbox));
+        let node_index = self.nodes.len();
+        self.nodes.push(Node { bbox, start, count: prims.len(), axis: 0 });
+        if prims.len() <= 1 {
+            return node_index;
+        }
+
+        let centroids = prims[1..].iter()
+            .fold(Aabb::new(prims[0].centroid, prims[0].centroid),
+                  |b, p| b.surrounding(Aabb::new(p.centroid, p.centroid)));
+        let extent = centroids.max - centroids.min;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+        if extent[axis] <= 0.0 {
+            // all centroids coincide, nothing to split on
+            return node_index;
+        }
+
+        let sah_mid = if depth < MAX_SAH_DEPTH {
+            match sah_split(prims, bbox, centroids, axis) {
+                Some(mid) => Some(mid),
+                None => return node_index,
+            }
+        } else {
+            None
+        };
+        let mid = match sah_mid {
+            Some(mid) if mid > 0 && mid < prims.len() => mid,
+            _ => {
+                // midpoint split on the median centroid
+                let mid = prims.len() / 2;
+                prims.select_nth_unstable_by(mid, |a, b| {
+                    a.centroid[axis].partial_cmp(&b.centroid[axis]).unwrap()
+                });
+                mid
+            }
+        };
+
+        let (left, right) = prims.split_at_mut(mid);
+        self.build_node(left, start, depth + 1);
+        let right_index = self.build_node(right, start + mid, depth + 1);
+        self.nodes[node_index] = Node { bbox, start: right_index, count: 0, axis };
+        node_index
+    }
+
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| node.bbox)
+    }
+
+    // `hit_primitive` intersects the primitive stored at the given index
+    pub fn hit<'a, F>(&self, ray: Ray, t_min: f32, t_max: f32, mut hit_primitive: F) -> Option<HitRecord<'a>>
+        where F: FnMut(usize, f32) -> Option<HitRecord<'a>>
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut hit_result: Option<HitRecord> = None;
+        let mut closest_so_far = t_max;
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_len = 1;
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node_index = stack[stack_len];
+            let node = &self.nodes[node_index];
+            if !node.bbox.hit(ray, t_min, closest_so_far) {
+                continue;
+            }
+            if node.count > 0 {
+                for index in node.start..node.start + node.count {
+                    if let Some(hit) = hit_primitive(index, closest_so_far) {
+                        closest_so_far = hit.t;
+                        hit_result = Some(hit);
+                    }
+                }
+            } else {
+                // visit the child nearer to the ray origin first
+                let left = node_index + 1;
+                let (near, far) = if ray.direction[node.axis] < 0.0 {
+                    (node.start, left)
+                } else {
+                    (left, node.start)
+                };
+                stack[stack_len] = far;
+                stack[stack_len + 1] = near;
+                stack_len += 2;
+            }
+        }
+        hit_result
+    }
+}
+
+// partitions `prims` at the cheapest bin boundary and returns the split point,
+// None when a leaf is cheaper
+fn sah_split(prims: &mut [Primitive], bbox: Aabb, centroids: Aabb, axis: usize) -> Option<usize> {
+    let min = centroids.min[axis];
+    let scale = NUM_BINS as f32 / (centroids.max[axis] - min);
+    let bin_of = |p: &Primitive| (((p.centroid[axis] - min) * scale) as usize).min(NUM_BINS - 1);
+
+    let mut counts = [0usize; NUM_BINS];
+    let mut bounds: [Option<Aabb>; NUM_BINS] = [None; NUM_BINS];
+    for p in prims.iter() {
+        let b = bin_of(p);
+        counts[b] += 1;
+        bounds[b] = surrounding(bounds[b], Some(p.bbox));
+    }
+
+    // cost of splitting after each bin, swept from both ends
+    let mut costs = [0.0f32; NUM_BINS - 1];
+    let mut count = 0;
+    let mut area_box: Option<Aabb> = None;
+    for i in 0..NUM_BINS - 1 {
+        count += counts[i];
+        area_box = surrounding(area_box, bounds[i]);
+        costs[i] = count as f32 * area_box.map_or(0.0, |b| b.surface_area());
+    }
+    count = 0;
+    area_box = None;
+    for i in (1..NUM_BINS).rev() {
+        count += counts[i];
+        area_box = surrounding(area_box, bounds[i]);
+        costs[i - 1] += count as f32 * area_box.map_or(0.0, |b| b.surface_area());
+    }
+
+    let (best_bin, best_cost) = costs
+        .iter()
+        .enumerate()
+        .fold((0, f32::MAX), |best, (i, &c)| if c < best.1 { (i, c) } else { best });
+    let split_cost = TRAVERSAL_COST + best_cost / bbox.surface_area();
+    let leaf_cost = prims.len() as f32;
+    if prims.len() <= MAX_LEAF_SIZE && leaf_cost <= split_cost {
+        return None;
+    }
+
+    let mut mid = 0;
+    for i in 0..prims.len() {
+        if bin_of(&prims[i]) <= best_bin {
+            prims.swap(i, mid);
+            mid += 1;
+        }
+    }
+    Some(mid)
+}
+
+fn surrounding(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.surrounding(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use rand::prelude::*;
+    use crate::{
+        hitable::{Sphere, World},
+        shapes::{Plane, Rect, Cuboid, Triangle, Disk, Cylinder},
+        material::{Material, Lambertian},
+    };
+
+    fn random_point<R: Rng>(rng: &mut R, extent: f32) -> Vec3 {
+        Vec3::new(rng.gen_range(-extent, extent), rng.gen_range(-extent, extent), rng.gen_range(-extent, extent))
+    }
+
+    // the same mix of primitives for the same seed, including unbounded planes the
+    // hierarchy leaves out and spheres moving over the shutter interval
+    fn random_objects(seed: u64) -> Vec<Box<dyn Hitable>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Vec3::ones()));
+        let mut objects: Vec<Box<dyn Hitable>> = Vec::new();
+        for i in 0..300 {
+            let p = random_point(&mut rng, 10.0);
+            let size = rng.gen_range(0.1, 1.5);
+            let m = material.clone();
+            objects.push(match i % 8 {
+                0 => Box::new(Sphere::new(p, size, m)),
+                1 => Box::new(Sphere::moving(p, p + random_point(&mut rng, 2.0), size, m)),
+                2 => Box::new(Rect::new(p, p + Vec3::new(size, 0.0, size), m).unwrap()),
+                3 => Box::new(Cuboid::new(p, p + Vec3::new(size, size * 0.5, size * 2.0), m)),
+                4 => Box::new(Triangle::new(p, p + random_point(&mut rng, size), p + random_point(&mut rng, size), m)),
+                5 => Box::new(Disk::new(p, random_point(&mut rng, 1.0), size, m)),
+                6 => Box::new(Cylinder::new(p, size * 0.5, size, m)),
+                _ if i % 64 == 7 => Box::new(Plane::new(p * 2.0, random_point(&mut rng, 1.0), m)),
+                _ => Box::new(Sphere::new(p, size * 0.25, m)),
+            });
+        }
+        objects
+    }
+
+    #[test]
+    fn hierarchy_finds_the_closest_hit_of_a_linear_scan() {
+        let linear = World::new(random_objects(7));
+        let mut hierarchy = World::new(random_objects(7));
+        hierarchy.build_bvh();
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut hits = 0;
+        for _ in 0..5000 {
+            let ray = Ray::new(random_point(&mut rng, 15.0), random_point(&mut rng, 1.0), rng.gen());
+            match (linear.hit(ray, 0.001, f32::MAX), hierarchy.hit(ray, 0.001, f32::MAX)) {
+                (Some(a), Some(b)) => {
+                    assert_eq!(a.t, b.t, "{:?}", ray);
+                    assert!((a.p - b.p).length() < 1e-4);
+                    hits += 1;
+                }
+                (None, None) => {}
+                (a, b) => panic!("{:?} hit {:?} without and {:?} with the hierarchy", ray, a.map(|h| h.t), b.map(|h| h.t)),
+            }
+        }
+        // most rays should hit something for the comparison to mean much
+        assert!(hits > 2500, "{} hits", hits);
+    }
+}
@@ -1,10 +1,50 @@
-use rand::prelude::*;
-
 use crate::{
     vec3::{Vec3},
     ray::{Ray},
+    sampler::{Sampler},
 };
 
+// the camera as described by a scene, turned into a `Camera` once the aspect is known
+#[derive(Copy, Clone, Debug)]
+pub struct CameraSettings {
+    pub look_from: Vec3,
+    pub look_to: Vec3,
+    pub vup: Vec3,
+    pub vfov: f32,
+    pub aperture: f32,
+    // defaults to the distance between look_from and look_to
+    pub focus_dist: Option<f32>,
+    // the interval the rays are spread over, an instant when open equals close
+    pub shutter_open: f32,
+    pub shutter_close: f32,
+}
+
+impl CameraSettings {
+    // fails for a camera without a view direction, or without a way up across it,
+    // which would render nothing but NaNs
+    pub fn validate(&self) -> Result<(), String> {
+        let view = self.look_to - self.look_from;
+        if view == Vec3::zeros() {
+            return Err("camera look_from and look_to must differ".to_string());
+        }
+        // the sine of the angle between them, NaN for a zero vup
+        let across = self.vup.cross(view).length() / (self.vup.length() * view.length());
+        if across > 1e-6 {
+            Ok(())
+        } else {
+            Err("camera vup must not be parallel to the view direction".to_string())
+        }
+    }
+
+    pub fn camera(&self, aspect: f32) -> Camera {
+        let focus_dist = self.focus_dist.unwrap_or_else(|| (self.look_from - self.look_to).length());
+        let mut camera = Camera::new(self.look_from, self.look_to, self.vup, self.vfov, aspect, self.aperture, focus_dist);
+        camera.time0 = self.shutter_open;
+        camera.time1 = self.shutter_close;
+        camera
+    }
+}
+
 pub struct Camera {
     pub origin: Vec3,
     pub lower_left_corner: Vec3,
@@ -14,6 +54,9 @@ pub struct Camera {
     pub v: Vec3,
     pub w: Vec3,
     pub lens_radius: f32,
+    // shutter interval
+    pub time0: f32,
+    pub time1: f32,
 }
 
 impl Camera {
@@ -31,30 +74,44 @@ impl Camera {
         let horizontal = u * half_width * focus_dist * 2.0;
         let vertical = v * half_height * focus_dist * 2.0;
         Camera {
-            origin: origin,
-            lower_left_corner: lower_left_corner,
-            horizontal: horizontal,
-            vertical: vertical,
-            u: u,
-            v: v,
-            w: w,
-            lens_radius: lens_radius,
+            origin,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            u,
+            v,
+            w,
+            lens_radius,
+            time0: 0.0,
+            time1: 0.0,
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32, rng: &mut ThreadRng) -> Ray {
-        let rd = random_in_unit_disk(rng) * self.lens_radius;
+    pub fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Ray {
+        let rd = random_in_unit_disk(sampler.next_2d()) * self.lens_radius;
         let offset = self.u * rd.x + self.v * rd.y;
+        // an instant shutter leaves the sample dimension to the rest of the path
+        let time = if self.time1 > self.time0 {
+            self.time0 + (self.time1 - self.time0) * sampler.next_1d()
+        } else {
+            self.time0
+        };
         Ray::new(self.origin + offset,
-                 self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset)
+                 self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
+                 time)
     }
 }
 
-fn random_in_unit_disk(rng: &mut ThreadRng) -> Vec3 {
-    loop {
-        let p = Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), 0.0) * 2.0 - Vec3::new(1.0, 1.0, 0.0);
-        if p.dot(p) < 1.0 {
-            return p;
-        }
+// shirley's concentric mapping, keeps the strata of the sample intact
+fn random_in_unit_disk((u1, u2): (f32, f32)) -> Vec3 {
+    let (a, b) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::zeros();
     }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, std::f32::consts::FRAC_PI_4 * (b / a))
+    } else {
+        (b, std::f32::consts::FRAC_PI_2 - std::f32::consts::FRAC_PI_4 * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
 }
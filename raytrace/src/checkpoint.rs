@@ -0,0 +1,177 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    vec3::{Vec3},
+    renderer::{Accumulator, PixelStats, Splat, Settings},
+    camera::{CameraSettings},
+};
+
+const MAGIC: &[u8; 8] = b"RTCKPT03";
+
+// identifies what a checkpoint is a render of, the scene file as written and the
+// settings and camera after the command line overrides that change what a sample
+// computes, the sample budget and tiles may change between runs and the color
+// settings only change how the image is shown
+pub fn fingerprint(scene_source: &[u8], settings: &Settings, camera: &CameraSettings) -> u64 {
+    let Settings { sampler, max_depth, filter, spectral, .. } = *settings;
+    let described = format!("{:?} {} {:?} {} {:?}", sampler, max_depth, filter, spectral, camera);
+    // fnv-1a, stable across runs and builds unlike the std hashers
+    scene_source
+        .iter()
+        .chain(described.as_bytes())
+        .fold(0xcbf2_9ce4_8422_2325u64, |h, &b| (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+// written next to the checkpoint and moved over it, so a crash while writing
+// leaves the previous checkpoint intact
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    PathBuf::from(temp)
+}
+
+// little endian: the magic, width, height, seed and fingerprint as u64, passes as
+// u32, then the sum, squared luminance sum and sample count of every pixel followed
+// by the splatted sum and weight of every pixel
+pub fn save(path: &Path, settings: &Settings, fingerprint: u64, accum: &Accumulator) -> io::Result<()> {
+    let temp = temp_path(path);
+    let mut file = BufWriter::new(File::create(&temp)?);
+    file.write_all(MAGIC)?;
+    for v in &[accum.width as u64, accum.height as u64, settings.seed, fingerprint] {
+        file.write_all(&v.to_le_bytes())?;
+    }
+    file.write_all(&accum.passes.to_le_bytes())?;
+    for p in &accum.pixels {
+        for c in &[p.sum.x, p.sum.y, p.sum.z, p.sum_sq] {
+            file.write_all(&c.to_le_bytes())?;
+        }
+        file.write_all(&p.samples.to_le_bytes())?;
+    }
+    for s in &accum.splats {
+        for c in &[s.sum.x, s.sum.y, s.sum.z, s.weight] {
+            file.write_all(&c.to_le_bytes())?;
+        }
+    }
+    file.into_inner()?.sync_all()?;
+    fs::rename(temp, path)
+}
+
+// fails unless the checkpoint was rendered with the size and seed in `settings`,
+// and from the same scene with the same settings as `fingerprint` says
+pub fn load(path: &Path, settings: &Settings, fingerprint: u64) -> io::Result<Accumulator> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 8];
+    file.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a raytrace checkpoint".to_string()));
+    }
+    let (width, height, seed) = (read_u64(&mut file)?, read_u64(&mut file)?, read_u64(&mut file)?);
+    if (width, height, seed) != (settings.width as u64, settings.height as u64, settings.seed) {
+        return Err(invalid_data(format!("checkpoint is {}x{} with seed {}, the render is {}x{} with seed {}",
+                                        width, height, seed, settings.width, settings.height, settings.seed)));
+    }
+    if read_u64(&mut file)? != fingerprint {
+        return Err(invalid_data("checkpoint is of a different scene or render settings".to_string()));
+    }
+    let mut accum = Accumulator::new(settings.width, settings.height);
+    accum.passes = read_u32(&mut file)?;
+    for p in accum.pixels.iter_mut() {
+        let mut c = [0.0f32; 4];
+        for v in c.iter_mut() {
+            *v = f32::from_bits(read_u32(&mut file)?);
+        }
+        *p = PixelStats { sum: Vec3::new(c[0], c[1], c[2]), sum_sq: c[3], samples: read_u32(&mut file)? };
+        accum.total_samples += p.samples as u64;
+    }
+    for s in accum.splats.iter_mut() {
+        let mut c = [0.0f32; 4];
+        for v in c.iter_mut() {
+            *v = f32::from_bits(read_u32(&mut file)?);
+        }
+        *s = Splat { sum: Vec3::new(c[0], c[1], c[2]), weight: c[3] };
+    }
+    Ok(accum)
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn accumulator(settings: &Settings) -> Accumulator {
+        let mut accum = Accumulator::new(settings.width, settings.height);
+        for (i, p) in accum.pixels.iter_mut().enumerate() {
+            p.add(Vec3::new(i as f32, 0.5, 0.25));
+        }
+        accum.splats[3] = Splat { sum: Vec3::new(1.0, 2.0, 3.0), weight: 0.75 };
+        accum.total_samples = accum.pixels.len() as u64;
+        accum.passes = 1;
+        accum
+    }
+
+    fn roundtrip(name: &str) {
+        let settings = Settings { width: 4, height: 3, ..Settings::default() };
+        let path = env::temp_dir().join(format!("raytrace-{}-{}", std::process::id(), name));
+        let accum = accumulator(&settings);
+        save(&path, &settings, 42, &accum).unwrap();
+        assert!(!temp_path(&path).exists());
+
+        let loaded = load(&path, &settings, 42).unwrap();
+        assert_eq!(loaded.passes, 1);
+        assert_eq!(loaded.total_samples, accum.total_samples);
+        assert!(loaded.pixels.iter().zip(&accum.pixels).all(|(a, b)| a.sum == b.sum && a.samples == b.samples));
+        assert!(loaded.splats.iter().zip(&accum.splats).all(|(a, b)| a.sum == b.sum && a.weight == b.weight));
+
+        assert!(load(&path, &settings, 43).is_err());
+        assert!(load(&path, &Settings { seed: 1, ..settings }, 42).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn checkpoints_resume_only_the_same_render() {
+        roundtrip("render.ckpt");
+        // the temporary file must not be the checkpoint itself
+        roundtrip("render.tmp");
+    }
+
+    #[test]
+    fn fingerprint_covers_the_scene_and_settings() {
+        let settings = Settings::default();
+        let camera = CameraSettings {
+            look_from: Vec3::new(0.0, 0.0, 1.0),
+            look_to: Vec3::zeros(),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 40.0,
+            aperture: 0.0,
+            focus_dist: None,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        };
+        let base = fingerprint(b"scene", &settings, &camera);
+        assert_eq!(base, fingerprint(b"scene", &settings, &camera));
+        assert_ne!(base, fingerprint(b"scene 2", &settings, &camera));
+        assert_ne!(base, fingerprint(b"scene", &Settings { max_depth: 3, ..settings }, &camera));
+        assert_ne!(base, fingerprint(b"scene", &Settings { spectral: true, ..settings }, &camera));
+        // more samples resume the same render
+        assert_eq!(base, fingerprint(b"scene", &Settings { num_samples: 1024, ..settings }, &camera));
+        assert_ne!(base, fingerprint(b"scene", &settings, &CameraSettings { vfov: 41.0, ..camera }));
+    }
+}
@@ -0,0 +1,125 @@
+use std::str::FromStr;
+
+use serde::{Deserialize};
+
+use crate::{
+    vec3::{Vec3},
+    random,
+    sampler::{unit_float},
+};
+
+// compresses radiance into [0, 1]
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToneMap {
+    // cuts everything above one
+    Clamp,
+    // scales by 1 / (1 + luminance), keeps the hue
+    Reinhard,
+    // narkowicz's fit of the aces reference rendering transform
+    Aces,
+    // hable's uncharted 2 curve
+    Filmic,
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ToneMap, String> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            "filmic" => Ok(ToneMap::Filmic),
+            _ => Err(format!("unknown tone map {}, expected clamp, reinhard, aces or filmic", s)),
+        }
+    }
+}
+
+// encoding of the tone mapped values in the 8 bit output
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transfer {
+    Srgb,
+    // square root, what the renderer used to write
+    Gamma2,
+    Linear,
+}
+
+// how linear radiance becomes displayable 8 bit color, float outputs stay linear
+#[derive(Copy, Clone, Debug)]
+pub struct ColorSettings {
+    // in stops, each one doubles the brightness
+    pub exposure: f32,
+    pub tone_map: ToneMap,
+    pub transfer: Transfer,
+    // noise of a step either way before quantizing, hides banding in gradients
+    pub dither: bool,
+}
+
+impl Default for ColorSettings {
+    fn default() -> ColorSettings {
+        ColorSettings { exposure: 0.0, tone_map: ToneMap::Clamp, transfer: Transfer::Srgb, dither: false }
+    }
+}
+
+impl ColorSettings {
+    // `pixel` seeds the dither so output is the same on every run
+    pub fn to_rgb8(&self, col: Vec3, pixel: usize) -> [u8; 3] {
+        let mapped = self.tone_map(col * self.exposure.exp2());
+        let mut rgb = [0u8; 3];
+        for (channel, (out, c)) in rgb.iter_mut().zip(&[mapped.x, mapped.y, mapped.z]).enumerate() {
+            // nan ends up black too
+            let c = if *c > 0.0 { c.min(1.0) } else { 0.0 };
+            let encoded = match self.transfer {
+                Transfer::Srgb => linear_to_srgb(c),
+                Transfer::Gamma2 => c.sqrt(),
+                Transfer::Linear => c,
+            };
+            let noise = if self.dither { triangle_noise(pixel, channel) } else { 0.0 };
+            *out = (encoded * 255.0 + 0.5 + noise).clamp(0.0, 255.0) as u8;
+        }
+        rgb
+    }
+
+    fn tone_map(&self, col: Vec3) -> Vec3 {
+        match self.tone_map {
+            ToneMap::Clamp => col,
+            ToneMap::Reinhard => col / (1.0 + luminance(col).max(0.0)),
+            ToneMap::Aces => map_channels(col, |x| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)),
+            ToneMap::Filmic => {
+                // linear white point of the curve
+                const WHITE: f32 = 11.2;
+                map_channels(col, |x| hable(2.0 * x) / hable(WHITE))
+            }
+        }
+    }
+}
+
+fn map_channels<F: Fn(f32) -> f32>(col: Vec3, f: F) -> Vec3 {
+    Vec3::new(f(col.x), f(col.y), f(col.z))
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+pub fn luminance(col: Vec3) -> f32 {
+    0.2126 * col.x + 0.7152 * col.y + 0.0722 * col.z
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 { 12.92 * c } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.040_45 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+// triangular distribution over (-1, 1)
+fn triangle_noise(pixel: usize, channel: usize) -> f32 {
+    let bits = random::hash(0, pixel as u64, channel as u64);
+    let (u1, u2) = (unit_float(bits), unit_float(bits << 24));
+    u1 - u2
+}
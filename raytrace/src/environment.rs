@@ -0,0 +1,191 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use image::codecs::hdr::HdrDecoder;
+use image::error::{DecodingError, ImageError, ImageFormatHint};
+
+use crate::{
+    vec3::{Vec3},
+    color::{luminance},
+    texture::{Image},
+};
+
+// equirectangular image of the radiance arriving from every direction, the
+// center of the image looks down -z and the top row straight up
+pub struct EnvironmentMap {
+    pub width: usize,
+    pub height: usize,
+    // linear, top row first
+    pub pixels: Vec<Vec3>,
+    // degrees counterclockwise about the y axis, seen from above
+    pub rotation: f32,
+    pub intensity: f32,
+    // picks rows, then a pixel within the row, by luminance
+    rows: Distribution,
+    columns: Vec<Distribution>,
+}
+
+impl EnvironmentMap {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3>, rotation: f32, intensity: f32) -> EnvironmentMap {
+        // rows near the poles cover less of the sphere
+        let columns: Vec<Distribution> = (0..height)
+            .map(|y| {
+                let sin_theta = (std::f32::consts::PI * (y as f32 + 0.5) / height as f32).sin();
+                Distribution::new(pixels[y * width..(y + 1) * width].iter().map(|&p| luminance(p) * sin_theta).collect())
+            })
+            .collect();
+        let rows = Distribution::new(columns.iter().map(|c| c.integral).collect());
+        EnvironmentMap { width, height, pixels, rotation, intensity, rows, columns }
+    }
+
+    // radiance hdr and openexr files keep their values, other images are taken
+    // to be srgb
+    pub fn load(path: &Path, rotation: f32, intensity: f32) -> image::ImageResult<EnvironmentMap> {
+        let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+        let (width, height, pixels) = match extension.as_deref() {
+            Some("hdr") => {
+                let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+                let meta = decoder.metadata();
+                let pixels = decoder.read_image_hdr()?.into_iter().map(|p| Vec3::new(p[0], p[1], p[2])).collect();
+                (meta.width as usize, meta.height as usize, pixels)
+            }
+            Some("exr") => load_exr(path)?,
+            _ => {
+                let Image { width, height, pixels } = Image::load(path)?;
+                (width, height, pixels)
+            }
+        };
+        Ok(EnvironmentMap::new(width, height, pixels, rotation, intensity))
+    }
+
+    // image coordinates in [0, 1] of a direction in world space
+    fn direction_to_uv(&self, direction: Vec3) -> (f32, f32) {
+        let d = rotate_y(direction.make_unit_vector(), -self.rotation);
+        let u = 0.5 + d.x.atan2(-d.z) / (2.0 * std::f32::consts::PI);
+        let v = d.y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+        (u, v)
+    }
+
+    fn uv_to_direction(&self, u: f32, v: f32) -> Vec3 {
+        let phi = (u - 0.5) * 2.0 * std::f32::consts::PI;
+        let theta = v * std::f32::consts::PI;
+        let d = Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos());
+        rotate_y(d, self.rotation)
+    }
+
+    fn pixel(&self, u: f32, v: f32) -> (usize, usize) {
+        let x = ((u * self.width as f32) as usize).min(self.width - 1);
+        let y = ((v * self.height as f32) as usize).min(self.height - 1);
+        (x, y)
+    }
+
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        if self.pixels.is_empty() {
+            return Vec3::zeros();
+        }
+        let (u, v) = self.direction_to_uv(direction);
+        let (x, y) = self.pixel(u, v);
+        self.pixels[y * self.width + x] * self.intensity
+    }
+
+    // a direction picked in proportion to the luminance arriving along it, and its
+    // solid angle density
+    pub fn sample(&self, (u1, u2): (f32, f32)) -> (Vec3, f32) {
+        if self.pixels.is_empty() {
+            return (Vec3::new(0.0, 1.0, 0.0), 0.0);
+        }
+        let (v, y) = self.rows.sample(u2);
+        let (u, _) = self.columns[y].sample(u1);
+        let direction = self.uv_to_direction(u, v);
+        (direction, self.pdf(direction))
+    }
+
+    pub fn pdf(&self, direction: Vec3) -> f32 {
+        if self.pixels.is_empty() {
+            return 0.0;
+        }
+        let (u, v) = self.direction_to_uv(direction);
+        let (x, y) = self.pixel(u, v);
+        let sin_theta = (std::f32::consts::PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let pdf_uv = if self.rows.integral > 0.0 {
+            self.columns[y].func[x] / self.rows.integral
+        } else {
+            1.0
+        };
+        // the image spans 2 pi by pi radians
+        pdf_uv / (2.0 * std::f32::consts::PI * std::f32::consts::PI * sin_theta)
+    }
+}
+
+// the first layer with rgb channels, alpha is ignored
+fn load_exr(path: &Path) -> image::ImageResult<(usize, usize, Vec<Vec3>)> {
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        path,
+        |size, _| (size.width(), vec![Vec3::zeros(); size.width() * size.height()]),
+        |(width, pixels): &mut (usize, Vec<Vec3>), position, (r, g, b, _a): (f32, f32, f32, f32)| {
+            pixels[position.y() * *width + position.x()] = Vec3::new(r, g, b);
+        },
+    ).map_err(|e| ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("OpenEXR".to_string()), e)))?;
+    let size = image.layer_data.size;
+    let (_, pixels) = image.layer_data.channel_data.pixels;
+    Ok((size.width(), size.height(), pixels))
+}
+
+fn rotate_y(d: Vec3, degrees: f32) -> Vec3 {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    Vec3::new(cos * d.x + sin * d.z, d.y, -sin * d.x + cos * d.z)
+}
+
+// piecewise constant density over [0, 1], uniform when the function is all zero
+struct Distribution {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    // of the function over [0, 1]
+    integral: f32,
+}
+
+impl Distribution {
+    fn new(func: Vec<f32>) -> Distribution {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i] / n as f32;
+        }
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 { *c / integral } else { i as f32 / n as f32 };
+        }
+        Distribution { func, cdf, integral }
+    }
+
+    // a point in [0, 1] and the segment it lies in
+    fn sample(&self, u: f32) -> (f32, usize) {
+        let n = self.func.len();
+        let i = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(n - 1);
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let du = if width > 0.0 { ((u - self.cdf[i]) / width).clamp(0.0, 1.0) } else { 0.5 };
+        ((i as f32 + du) / n as f32, i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    #[test]
+    fn exr_keeps_values_above_one() {
+        let path = env::temp_dir().join(format!("raytrace-{}-environment.exr", process::id()));
+        exr::prelude::write_rgb_file(&path, 4, 2, |x, y| (x as f32 * 10.0, y as f32 + 0.25, 0.5f32)).unwrap();
+        let map = EnvironmentMap::load(&path, 0.0, 1.0);
+        fs::remove_file(&path).unwrap();
+        let map = map.unwrap();
+        assert_eq!((map.width, map.height), (4, 2));
+        assert_eq!(map.pixels[3], Vec3::new(30.0, 0.25, 0.5));
+        assert_eq!(map.pixels[6], Vec3::new(20.0, 1.25, 0.5));
+    }
+}
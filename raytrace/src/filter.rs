@@ -0,0 +1,168 @@
+use std::str::FromStr;
+
+use serde::{Deserialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    // mitchell-netravali with b = c = 1/3
+    Mitchell,
+    // windowed sinc, negative lobes sharpen edges
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn default_radius(self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FilterKind, String> {
+        match s {
+            "box" => Ok(FilterKind::Box),
+            "tent" => Ok(FilterKind::Tent),
+            "gaussian" => Ok(FilterKind::Gaussian),
+            "mitchell" => Ok(FilterKind::Mitchell),
+            "lanczos" => Ok(FilterKind::Lanczos),
+            _ => Err(format!("unknown filter {}, expected box, tent, gaussian, mitchell or lanczos", s)),
+        }
+    }
+}
+
+// pixel reconstruction filter, each sample adds to every pixel whose center is
+// within `radius` pixels of it, weighted by the filter
+#[derive(Copy, Clone, Debug)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f32,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Filter {
+        Filter { kind, radius: kind.default_radius() }
+    }
+
+    // the number of neighbouring pixels a sample can reach
+    pub fn margin(&self) -> usize {
+        (self.radius - 0.5).max(0.0).ceil() as usize
+    }
+
+    // separable in the offsets from the pixel center, in pixels
+    pub fn eval(&self, dx: f32, dy: f32) -> f32 {
+        self.eval_1d(dx) * self.eval_1d(dy)
+    }
+
+    fn eval_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+        if x >= self.radius {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => self.radius - x,
+            FilterKind::Gaussian => {
+                const ALPHA: f32 = 2.0;
+                // shifted to reach zero at the radius
+                (-ALPHA * x * x).exp() - (-ALPHA * self.radius * self.radius).exp()
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / self.radius),
+            FilterKind::Lanczos => sinc(x) * sinc(x / self.radius),
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter::new(FilterKind::Box)
+    }
+}
+
+// x in [0, 2]
+fn mitchell(x: f32) -> f32 {
+    const B: f32 = 1.0 / 3.0;
+    const C: f32 = 1.0 / 3.0;
+    if x < 1.0 {
+        ((12.0 - 9.0 * B - 6.0 * C) * x * x * x + (-18.0 + 12.0 * B + 6.0 * C) * x * x + (6.0 - 2.0 * B)) / 6.0
+    } else {
+        ((-B - 6.0 * C) * x * x * x + (6.0 * B + 30.0 * C) * x * x + (-12.0 * B - 48.0 * C) * x + (8.0 * B + 24.0 * C)) / 6.0
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x < 1e-5 {
+        return 1.0;
+    }
+    let px = std::f32::consts::PI * x;
+    px.sin() / px
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [FilterKind; 5] = [FilterKind::Box, FilterKind::Tent, FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::Lanczos];
+
+    #[test]
+    fn filters_vanish_outside_their_radius() {
+        for &kind in &KINDS {
+            for &radius in &[0.5, kind.default_radius(), 2.5] {
+                let filter = Filter { kind, radius };
+                assert!(filter.eval(0.0, 0.0) > 0.0);
+                for &x in &[radius, radius + 0.01, radius + 1.0, 10.0] {
+                    assert_eq!(filter.eval(x, 0.0), 0.0, "{:?} of radius {} at {}", kind, radius, x);
+                    assert_eq!(filter.eval(0.0, -x), 0.0, "{:?} of radius {} at {}", kind, radius, -x);
+                    assert_eq!(filter.eval(x, x), 0.0);
+                }
+                // and reach it smoothly, apart from the box
+                if kind != FilterKind::Box {
+                    assert!(filter.eval(radius - 1e-3, 0.0).abs() < 1e-2, "{:?} of radius {}", kind, radius);
+                }
+            }
+        }
+    }
+
+    // splats evenly spread samples of a constant color onto a small film the way
+    // the renderer does
+    fn splat_constant(filter: Filter, color: f32) -> Vec<(f32, f32)> {
+        let (width, height, per_pixel) = (7, 5, 8);
+        let mut film = vec![(0.0f32, 0.0f32); width * height];
+        for sy in 0..height * per_pixel {
+            for sx in 0..width * per_pixel {
+                let (fx, fy) = ((sx as f32 + 0.5) / per_pixel as f32, (sy as f32 + 0.5) / per_pixel as f32);
+                for y in 0..height {
+                    for x in 0..width {
+                        let weight = filter.eval(fx - (x as f32 + 0.5), fy - (y as f32 + 0.5));
+                        let (sum, total) = &mut film[y * width + x];
+                        *sum += color * weight;
+                        *total += weight;
+                    }
+                }
+            }
+        }
+        film
+    }
+
+    #[test]
+    fn splatting_reproduces_a_constant_image() {
+        for &kind in &KINDS {
+            for &radius in &[0.5, kind.default_radius()] {
+                for (sum, weight) in splat_constant(Filter { kind, radius }, 0.25) {
+                    assert!(weight > 1e-4, "{:?} of radius {} leaves a pixel without weight", kind, radius);
+                    assert!((sum / weight - 0.25).abs() < 1e-5, "{:?} of radius {} gives {}", kind, radius, sum / weight);
+                }
+            }
+        }
+    }
+}
@@ -1,74 +1,216 @@
+use std::sync::Arc;
+
 use crate::{
     vec3::{Vec3},
     ray::{Ray},
+    sampler::{Sampler},
     material::{Material},
+    aabb::{Aabb},
+    bvh::{Bvh},
+    spectrum::{Wavelength},
 };
 
+const SPAWN_EPSILON: f32 = 0.0001;
+
 #[derive(Copy, Clone)]
-pub struct HitRecord {
+pub struct HitRecord<'a> {
     pub t: f32,
     pub p: Vec3,
+    // always faces against the incoming ray
     pub normal: Vec3,
-    pub material: Material,
+    // whether the ray hit the outside of the surface
+    pub front_face: bool,
+    // surface coordinates for texturing
+    pub u: f32,
+    pub v: f32,
+    // of the ray, carried over to the rays spawned here
+    pub time: f32,
+    pub wavelength: Wavelength,
+    pub absorption: Vec3,
+    pub material: &'a dyn Material,
 }
 
-#[derive(Copy, Clone)]
+impl<'a> HitRecord<'a> {
+    pub fn new(ray: Ray, t: f32, outward_normal: Vec3, u: f32, v: f32, material: &'a dyn Material) -> HitRecord<'a> {
+        let front_face = ray.direction.dot(outward_normal) < 0.0;
+        HitRecord {
+            t,
+            p: ray.point_at_parameter(t),
+            normal: if front_face { outward_normal } else { -outward_normal },
+            front_face,
+            u,
+            v,
+            time: ray.time,
+            wavelength: ray.wavelength,
+            absorption: ray.absorption,
+            material,
+        }
+    }
+
+    // starts the ray just off the surface, on the side it leaves towards, so it
+    // does not hit the same surface again through rounding errors
+    pub fn spawn_ray(&self, direction: Vec3) -> Ray {
+        let scale = self.p.x.abs().max(self.p.y.abs()).max(self.p.z.abs()).max(1.0);
+        let offset = self.normal * (SPAWN_EPSILON * scale);
+        let origin = if direction.dot(self.normal) > 0.0 { self.p + offset } else { self.p - offset };
+        Ray { wavelength: self.wavelength, absorption: self.absorption, ..Ray::new(origin, direction, self.time) }
+    }
+}
+
+pub trait Hitable: Send + Sync {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+    // None for unbounded primitives such as planes
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // objects that can be sampled as area lights implement these two
+
+    // solid angle density of `random` picking `direction` from `origin`
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3) -> f32 {
+        0.0
+    }
+
+    // direction from `origin` towards a random point on the object
+    fn random(&self, _origin: Vec3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+}
+
+#[derive(Clone)]
 pub struct Sphere {
+    // at time 0
     pub center: Vec3,
+    // how far the center moves by time 1, the sphere stays put outside of [0, 1]
+    pub motion: Vec3,
     pub radius: f32,
-    pub material: Material,
+    pub material: Arc<dyn Material>,
 }
 
 impl Sphere {
-    pub fn new(center: Vec3, radius: f32, material: Material) -> Sphere {
-        Sphere { center, radius, material }
+    pub fn new(center: Vec3, radius: f32, material: Arc<dyn Material>) -> Sphere {
+        Sphere { center, motion: Vec3::zeros(), radius, material }
+    }
+
+    // moves in a straight line from `center0` at time 0 to `center1` at time 1
+    pub fn moving(center0: Vec3, center1: Vec3, radius: f32, material: Arc<dyn Material>) -> Sphere {
+        Sphere { center: center0, motion: center1 - center0, radius, material }
+    }
+
+    pub fn center(&self, time: f32) -> Vec3 {
+        self.center + self.motion * time.clamp(0.0, 1.0)
     }
+}
 
-    pub fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
+impl Hitable for Sphere {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let center = self.center(ray.time);
+        let oc = ray.origin - center;
         let a = ray.direction.dot(ray.direction);
         let b = oc.dot(ray.direction);
         let c = oc.dot(oc) - self.radius * self.radius;
         let discriminant = b * b - a * c;
 
         if discriminant > 0.0 {
-            let temp = (-b - (b * b - a * c).sqrt()) / a;
-            if temp  < t_max && temp > t_min {
-                let hit_point = ray.point_at_parameter(temp);
-                return Some(HitRecord {
-                    t: temp,
-                    p: hit_point,
-                    normal: (hit_point - self.center) / self.radius,
-                    material: self.material,
-                });
-            } 
-        } 
+            let sqrt_d = discriminant.sqrt();
+            for &temp in &[(-b - sqrt_d) / a, (-b + sqrt_d) / a] {
+                if temp < t_max && temp > t_min {
+                    let outward_normal = (ray.point_at_parameter(temp) - center) / self.radius;
+                    let (u, v) = sphere_uv(outward_normal);
+                    return Some(HitRecord::new(ray, temp, outward_normal, u, v, &*self.material));
+                }
+            }
+        }
         None
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        let start = Aabb::new(self.center - r, self.center + r);
+        Some(start.surrounding(Aabb::new(start.min + self.motion, start.max + self.motion)))
+    }
+
+    // uniform over the cone the sphere subtends, or over all directions from inside
+    // it, only for spheres that keep still
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        if self.hit(Ray::new(origin, direction, 0.0), 0.0001, f32::MAX).is_none() {
+            return 0.0;
+        }
+        let distance_squared = (self.center - origin).squared_length();
+        if distance_squared <= self.radius * self.radius {
+            return 1.0 / (4.0 * std::f32::consts::PI);
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        1.0 / (2.0 * std::f32::consts::PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.squared_length();
+        let (r1, r2) = sampler.next_2d();
+        let phi = 2.0 * std::f32::consts::PI * r1;
+        let (z, w) = if distance_squared <= self.radius * self.radius {
+            (1.0 - 2.0 * r2, Vec3::new(0.0, 0.0, 1.0))
+        } else {
+            let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+            (1.0 + r2 * (cos_theta_max - 1.0), direction.make_unit_vector())
+        };
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+        let (u, v) = w.basis();
+        u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * z
+    }
+}
+
+// u goes around the y axis starting at -x, v from the bottom pole to the top
+fn sphere_uv(p: Vec3) -> (f32, f32) {
+    let theta = (-p.y).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z).atan2(p.x) + std::f32::consts::PI;
+    (phi / (2.0 * std::f32::consts::PI), theta / std::f32::consts::PI)
 }
 
 pub struct World {
-    pub spheres: Vec<Sphere>,
+    pub objects: Vec<Box<dyn Hitable>>,
+    bvh: Option<Bvh>,
+    // objects[..num_bounded] are covered by the bvh
+    num_bounded: usize,
 }
 
 impl World {
-    pub fn new(spheres: Vec<Sphere>) -> World {
-        World { spheres }
+    pub fn new(objects: Vec<Box<dyn Hitable>>) -> World {
+        World { objects, bvh: None, num_bounded: 0 }
     }
 
-    pub fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let mut hit_result: Option<HitRecord> = None;
-        let mut closest_so_far = t_max;
-        for sphere in self.spheres.iter() {
-            if let Some(hit) = sphere.hit(ray, t_min, closest_so_far) {
-                closest_so_far = if hit.t < closest_so_far {
-                    hit_result = Some(hit);
-                    hit.t
-                } else {
-                    closest_so_far
-                }
+    // call once the world is complete, before rendering
+    pub fn build_bvh(&mut self) {
+        // unbounded objects such as planes stay outside the hierarchy
+        let (mut bounded, unbounded): (Vec<_>, Vec<_>) = self.objects
+            .drain(..)
+            .partition(|o| o.bounding_box().is_some());
+        self.bvh = Some(Bvh::build(&mut bounded));
+        self.num_bounded = bounded.len();
+        self.objects = bounded;
+        self.objects.extend(unbounded);
+    }
+}
+
+impl Hitable for World {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (mut hit_result, linear) = match self.bvh {
+            Some(ref bvh) => (bvh.hit(ray, t_min, t_max, |i, t_max| self.objects[i].hit(ray, t_min, t_max)),
+                              &self.objects[self.num_bounded..]),
+            None => (None, &self.objects[..]),
+        };
+        let mut closest_so_far = hit_result.map_or(t_max, |hit| hit.t);
+        for object in linear.iter() {
+            if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+                closest_so_far = hit.t;
+                hit_result = Some(hit);
             }
         }
         hit_result
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|o| o.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| b.map(|b| acc.surrounding(b)))
+    }
 }
@@ -0,0 +1,27 @@
+pub mod vec3;
+pub mod ray;
+pub mod random;
+pub mod sampler;
+pub mod aabb;
+pub mod hitable;
+pub mod bvh;
+pub mod shapes;
+pub mod mesh;
+pub mod obj;
+pub mod ply;
+pub mod transform;
+pub mod medium;
+pub mod camera;
+pub mod material;
+pub mod texture;
+pub mod light;
+pub mod background;
+pub mod environment;
+pub mod filter;
+pub mod tile;
+pub mod color;
+pub mod spectrum;
+pub mod renderer;
+pub mod scene;
+pub mod output;
+pub mod checkpoint;
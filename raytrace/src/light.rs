@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use crate::{
+    vec3::{Vec3},
+    hitable::{Hitable},
+    environment::{EnvironmentMap},
+};
+
+// lights sampled directly at every diffuse bounce
+pub enum Light {
+    // emissive object that implements `Hitable::random` and `Hitable::pdf_value`
+    Area(Box<dyn Hitable>),
+    Point { position: Vec3, intensity: Vec3 },
+    // `direction` is the way the light travels
+    Directional { direction: Vec3, irradiance: Vec3 },
+    // the background, sampled by its brightness
+    Environment(Arc<EnvironmentMap>),
+}
+
+// solid angle density of sampling `direction` from `origin` by picking one of
+// `lights` uniformly and sampling it, delta lights never match a direction
+pub fn pdf_value(lights: &[Light], origin: Vec3, direction: Vec3) -> f32 {
+    if lights.is_empty() {
+        return 0.0;
+    }
+    let sum: f32 = lights
+        .iter()
+        .map(|light| match light {
+            Light::Area(object) => object.pdf_value(origin, direction),
+            Light::Environment(map) => map.pdf(direction),
+            _ => 0.0,
+        })
+        .sum();
+    sum / lights.len() as f32
+}
@@ -1,77 +1,415 @@
-use std::f32;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 extern crate minifb;
 use minifb::{Key, WindowOptions, Window};
 use rand::prelude::*;
+use structopt::StructOpt;
 
-mod vec3;
-mod ray;
-mod hitable;
-mod camera;
-mod material;
-mod renderer;
-use crate::{
+use raytrace::{
     vec3::{Vec3},
-    hitable::{Sphere, World},
-    camera::{Camera},
-    material::{Material},
+    hitable::{Hitable, Sphere, World},
+    camera::{CameraSettings},
+    material::{Lambertian, Metal, Dielectric},
+    background::{Background},
+    color::{ColorSettings, ToneMap},
+    renderer::{self, Settings, Accumulator},
+    scene::{Scene},
+    sampler::{SamplerKind},
+    tile::{TileOrder},
+    filter::{Filter, FilterKind},
+    output,
+    checkpoint,
 };
 
-const WIDTH: usize = 800;
-const HEIGHT: usize = 400;
-
-fn random_scene(rng: &mut ThreadRng) -> Vec<Sphere> {
+fn random_scene<R: Rng>(rng: &mut R) -> Vec<Box<dyn Hitable>> {
     let n = 5;
-    let mut spheres = Vec::new();
-    spheres.push(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Material::lambertian(Vec3::new(0.5, 0.5, 0.5))));
+    let mut spheres: Vec<Box<dyn Hitable>> = Vec::new();
+    spheres.push(Box::new(Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))))));
     for a in -n..n {
         for b in -n..n {
             let choose_mat = rng.gen::<f32>();
             let center = Vec3::new(a as f32 + 0.9 * rng.gen::<f32>(), 0.2, b as f32 + 0.9 * rng.gen::<f32>());
             if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_mat < 0.8 { // diffuse
-                    spheres.push(Sphere::new(center, 0.2,
-                                 Material::lambertian(Vec3::new(rng.gen::<f32>() * rng.gen::<f32>(),
-                                                                rng.gen::<f32>() * rng.gen::<f32>(),
-                                                                rng.gen::<f32>() * rng.gen::<f32>()))));
+                    spheres.push(Box::new(Sphere::new(center, 0.2,
+                                 Arc::new(Lambertian::new(Vec3::new(rng.gen::<f32>() * rng.gen::<f32>(),
+                                                                    rng.gen::<f32>() * rng.gen::<f32>(),
+                                                                    rng.gen::<f32>() * rng.gen::<f32>()))))));
                 } else if choose_mat < 0.95 { // metal
-                    spheres.push(Sphere::new(center, 0.2,
-                                 Material::metal(Vec3::new(0.5 * (1.0 + rng.gen::<f32>()),
-                                                           0.5 * (1.0 + rng.gen::<f32>()),
-                                                           0.5 * (1.0 + rng.gen::<f32>())),
-                                                 0.5 * rng.gen::<f32>())));
+                    spheres.push(Box::new(Sphere::new(center, 0.2,
+                                 Arc::new(Metal::new(Vec3::new(0.5 * (1.0 + rng.gen::<f32>()),
+                                                               0.5 * (1.0 + rng.gen::<f32>()),
+                                                               0.5 * (1.0 + rng.gen::<f32>())),
+                                                     0.5 * rng.gen::<f32>())))));
                 } else { // glass
-                    spheres.push(Sphere::new(center, 0.2, Material::dielectric(1.5)));
+                    spheres.push(Box::new(Sphere::new(center, 0.2, Arc::new(Dielectric::new(1.5)))));
                 }
             }
         }
     }
-    spheres.push(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, Material::dielectric(1.5)));
-    spheres.push(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, Material::lambertian(Vec3::new(0.4, 0.2, 0.1))));
-    spheres.push(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, Material::metal(Vec3::new(0.7, 0.6, 0.5), 0.0)));
+    spheres.push(Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, Arc::new(Dielectric::new(1.5)))));
+    spheres.push(Box::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, Arc::new(Lambertian::new(Vec3::new(0.4, 0.2, 0.1))))));
+    spheres.push(Box::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, Arc::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.0)))));
     spheres
 }
 
+fn default_scene(seed: u64) -> Scene {
+    let camera = CameraSettings {
+        look_from: Vec3::new(15.0, 2.0, 4.0),
+        look_to: Vec3::new(0.0, 0.0, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov: 15.0,
+        aperture: 0.05,
+        focus_dist: None,
+        shutter_open: 0.0,
+        shutter_close: 0.0,
+    };
+    let world = World::new(random_scene(&mut StdRng::seed_from_u64(seed)));
+    let settings = Settings { seed, ..Settings::default() };
+    Scene { settings, camera, world, background: Background::sky(), lights: Vec::new(), color: ColorSettings::default(), fog: None, media: false }
+}
+
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+    let v = s.split(',')
+        .map(|c| c.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|e| e.to_string())?;
+    match v[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("expected x,y,z but found {}", s)),
+    }
+}
+
+fn parse_positive<T>(s: &str) -> Result<T, String>
+    where T: FromStr + PartialOrd + Default + fmt::Display, T::Err: fmt::Display
+{
+    let value = s.parse::<T>().map_err(|e| e.to_string())?;
+    if value > T::default() {
+        Ok(value)
+    } else {
+        Err(format!("expected a positive value, found {}", value))
+    }
+}
+
+#[derive(StructOpt)]
+#[structopt(name = "raytrace", about = "Renders a scene in a window or to an image file")]
+struct Opt {
+    /// Scene description file (TOML), renders a random scene when omitted
+    #[structopt(short, long, parse(from_os_str))]
+    scene: Option<PathBuf>,
+    /// Image file to write, format chosen by extension (ppm, png or pfm)
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// Image file to write the number of samples taken per pixel to
+    #[structopt(long, parse(from_os_str))]
+    heat_map: Option<PathBuf>,
+    /// Checkpoint file, resumed from when it exists and rewritten while rendering
+    #[structopt(long, parse(from_os_str))]
+    checkpoint: Option<PathBuf>,
+    /// Seconds between checkpoint writes
+    #[structopt(long, default_value = "60")]
+    checkpoint_interval: u64,
+    /// Render without opening a window
+    #[structopt(long, requires = "output")]
+    headless: bool,
+    /// Image width in pixels
+    #[structopt(long, parse(try_from_str = parse_positive))]
+    width: Option<usize>,
+    /// Image height in pixels
+    #[structopt(long, parse(try_from_str = parse_positive))]
+    height: Option<usize>,
+    /// Samples per pixel
+    #[structopt(short = "n", long, parse(try_from_str = parse_positive))]
+    samples: Option<u32>,
+    /// Maximum number of ray bounces
+    #[structopt(long)]
+    max_depth: Option<u32>,
+    /// Camera position as x,y,z
+    #[structopt(long, parse(try_from_str = parse_vec3))]
+    look_from: Option<Vec3>,
+    /// Camera target as x,y,z
+    #[structopt(long, parse(try_from_str = parse_vec3))]
+    look_to: Option<Vec3>,
+    /// Lens aperture, 0 for a pinhole camera
+    #[structopt(long)]
+    aperture: Option<f32>,
+    /// Exposure adjustment in stops
+    #[structopt(long, allow_hyphen_values = true)]
+    exposure: Option<f32>,
+    /// Tone mapping operator: clamp, reinhard, aces or filmic
+    #[structopt(long)]
+    tone_map: Option<ToneMap>,
+    /// Dither the 8 bit output
+    #[structopt(long)]
+    dither: bool,
+    /// Seed for the random scene and the render, the same seed gives the same image
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// Stop sampling pixels once their relative error drops below this
+    #[structopt(long, parse(try_from_str = parse_positive))]
+    adaptive: Option<f32>,
+    /// Sample generator: independent, stratified, halton or sobol
+    #[structopt(long)]
+    sampler: Option<SamplerKind>,
+    /// Edge length of the render tiles in pixels
+    #[structopt(long, parse(try_from_str = parse_positive))]
+    tile_size: Option<usize>,
+    /// Order the tiles render in: scanline, spiral or hilbert
+    #[structopt(long)]
+    tile_order: Option<TileOrder>,
+    /// Pixel reconstruction filter: box, tent, gaussian, mitchell or lanczos
+    #[structopt(long)]
+    filter: Option<FilterKind>,
+    /// Filter radius in pixels, defaults to the filter's own
+    #[structopt(long, parse(try_from_str = parse_positive))]
+    filter_radius: Option<f32>,
+    /// Trace a single wavelength per path so dispersive glass splits light into colors
+    #[structopt(long)]
+    spectral: bool,
+    /// Number of render threads, defaults to one per core
+    #[structopt(short = "j", long)]
+    threads: Option<usize>,
+}
+
+impl Opt {
+    fn apply(&self, scene: &mut Scene) {
+        let settings = &mut scene.settings;
+        settings.width = self.width.unwrap_or(settings.width);
+        settings.height = self.height.unwrap_or(settings.height);
+        settings.num_samples = self.samples.unwrap_or(settings.num_samples);
+        settings.max_depth = self.max_depth.unwrap_or(settings.max_depth);
+        settings.seed = self.seed.unwrap_or(settings.seed);
+        settings.sampler = self.sampler.unwrap_or(settings.sampler);
+        settings.adaptive_threshold = self.adaptive.or(settings.adaptive_threshold);
+        settings.tile_size = self.tile_size.unwrap_or(settings.tile_size);
+        settings.tile_order = self.tile_order.unwrap_or(settings.tile_order);
+        if let Some(kind) = self.filter {
+            settings.filter = Filter::new(kind);
+        }
+        if let Some(radius) = self.filter_radius {
+            settings.filter.radius = radius;
+        }
+        settings.spectral |= self.spectral;
+        let color = &mut scene.color;
+        color.exposure = self.exposure.unwrap_or(color.exposure);
+        color.tone_map = self.tone_map.unwrap_or(color.tone_map);
+        color.dither |= self.dither;
+        let camera = &mut scene.camera;
+        camera.look_from = self.look_from.unwrap_or(camera.look_from);
+        camera.look_to = self.look_to.unwrap_or(camera.look_to);
+        camera.aperture = self.aperture.unwrap_or(camera.aperture);
+    }
+
+    fn save(&self, accum: &Accumulator, color: &ColorSettings) {
+        if let Some(ref path) = self.output {
+            save(path, accum.width, accum.height, &accum.image(), color);
+        }
+        // the heat map colors are meant to be shown as they are
+        if let Some(ref path) = self.heat_map {
+            save(path, accum.width, accum.height, &accum.heat_map(), &ColorSettings::default());
+        }
+    }
+}
+
+fn save(path: &Path, width: usize, height: usize, pixels: &[Vec3], color: &ColorSettings) {
+    output::save(path, width, height, pixels, color).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path.display(), e);
+        process::exit(1);
+    });
+}
+
+// how often the window and the progress bar are redrawn while rendering
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+const REPORT_INTERVAL: Duration = Duration::from_millis(250);
+
+const PROGRESS_BAR_WIDTH: usize = 40;
+
+// the pass being rendered, or the last one once done
+fn current_pass(accum: &Accumulator, settings: &Settings) -> u32 {
+    if accum.done(settings) { accum.passes } else { accum.passes + 1 }
+}
+
+// when this run started and how far along the render was then, resumed
+// renders start part way
+struct Progress {
+    start: Instant,
+    initial: f32,
+}
+
+impl Progress {
+    fn new(accum: &Accumulator, settings: &Settings) -> Progress {
+        Progress { start: Instant::now(), initial: accum.progress(settings) }
+    }
+
+    fn time_left(&self, accum: &Accumulator, settings: &Settings) -> Duration {
+        let progress = accum.progress(settings);
+        let gained = (progress - self.initial).max(1e-6);
+        self.start.elapsed().mul_f32((1.0 - progress) / gained)
+    }
+
+    fn title(&self, accum: &Accumulator, settings: &Settings) -> String {
+        format!("raytrace - pass {}, {:.0}%, {} left - ESC to stop",
+                current_pass(accum, settings), accum.progress(settings) * 100.0,
+                format_duration(self.time_left(accum, settings)))
+    }
+
+    fn report(&self, accum: &Accumulator, settings: &Settings) {
+        let progress = accum.progress(settings);
+        let filled = (progress * PROGRESS_BAR_WIDTH as f32) as usize;
+        eprint!("\r[{}{}] {:3.0}% pass {}, {} left ",
+                "#".repeat(filled), "-".repeat(PROGRESS_BAR_WIDTH - filled),
+                progress * 100.0, current_pass(accum, settings), format_duration(self.time_left(accum, settings)));
+    }
+}
+
+// rewrites the checkpoint file, if there is one, every `interval`, between passes
+// as only then are the splats of the sampled pixels in the accumulator
+struct Checkpointer<'a> {
+    path: Option<&'a Path>,
+    fingerprint: u64,
+    interval: Duration,
+    last: Instant,
+}
+
+impl<'a> Checkpointer<'a> {
+    fn tick(&mut self, settings: &Settings, accum: &Accumulator) {
+        if self.last.elapsed() >= self.interval {
+            self.save(settings, accum);
+        }
+    }
+
+    fn save(&mut self, settings: &Settings, accum: &Accumulator) {
+        if let Some(path) = self.path {
+            // losing a checkpoint is no reason to stop the render
+            if let Err(e) = checkpoint::save(path, settings, self.fingerprint, accum) {
+                eprintln!("{}: {}", path.display(), e);
+            }
+        }
+        self.last = Instant::now();
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{:.1}s", d.as_secs_f32())
+    }
+}
+
 fn main() {
-    let mut window = Window::new("Test - ESC to exit",
-                                 WIDTH,
-                                 HEIGHT,
+    let opt = Opt::from_args();
+
+    if let Some(threads) = opt.threads {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global().unwrap();
+    }
+
+    let mut scene = match opt.scene {
+        Some(ref path) => Scene::load(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            process::exit(1);
+        }),
+        None => default_scene(opt.seed.unwrap_or(0)),
+    };
+    opt.apply(&mut scene);
+    // the overrides may leave the camera looking nowhere
+    if let Err(e) = scene.camera.validate() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    scene.world.build_bvh();
+    let Settings { width, height, .. } = scene.settings;
+
+    // checkpoints only resume the scene file and settings they were rendered with
+    let source = opt.scene.as_ref().and_then(|path| fs::read(path).ok()).unwrap_or_default();
+    let fingerprint = checkpoint::fingerprint(&source, &scene.settings, &scene.camera);
+    let mut accum = match opt.checkpoint {
+        Some(ref path) if path.exists() => checkpoint::load(path, &scene.settings, fingerprint).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            process::exit(1);
+        }),
+        _ => Accumulator::new(width, height),
+    };
+    let mut checkpointer = Checkpointer {
+        path: opt.checkpoint.as_deref(),
+        fingerprint,
+        interval: Duration::from_secs(opt.checkpoint_interval),
+        last: Instant::now(),
+    };
+
+    let camera = scene.camera.camera(width as f32 / height as f32);
+
+    if opt.headless {
+        let progress = Progress::new(&accum, &scene.settings);
+        let mut last_report = progress.start;
+        while !accum.done(&scene.settings) {
+            renderer::render_pass(&scene, &camera, &mut accum, |_, accum| {
+                if last_report.elapsed() >= REPORT_INTERVAL {
+                    last_report = Instant::now();
+                    progress.report(accum, &scene.settings);
+                }
+                true
+            });
+            checkpointer.tick(&scene.settings, &accum);
+        }
+        progress.report(&accum, &scene.settings);
+        eprintln!();
+        checkpointer.save(&scene.settings, &accum);
+        opt.save(&accum, &scene.color);
+        return;
+    }
+
+    let mut window = Window::new("raytrace - ESC to exit",
+                                 width,
+                                 height,
                                  WindowOptions::default()).unwrap_or_else(|e| {
         panic!("{}", e);
     });
 
-    let look_from = Vec3::new(15.0, 2.0, 4.0);
-    let look_to = Vec3::new(0.0, 0.0, 0.0);
-    let dist_to_focus = (look_from - look_to).length();
-    let aperture = 0.05;
-    let camera = Camera::new(look_from, look_to, Vec3::new(0.0, 1.0, 0.0), 15.0, WIDTH as f32 / HEIGHT as f32, aperture, dist_to_focus);
-    let mut rng = rand::thread_rng();
-    let world = World::new(random_scene(&mut rng));
+    // one sample per pixel at a time so the image refines while we watch, the
+    // window shows each tile as it completes with its pixels averaged and the
+    // filtered image once the pass is done
+    let mut buffer = renderer::to_bgra_buffer(&scene.color, &accum.image());
+    let progress = Progress::new(&accum, &scene.settings);
+    let mut last_frame = progress.start;
+    while !accum.done(&scene.settings) && window.is_open() && !window.is_key_down(Key::Escape) {
+        let completed = renderer::render_pass(&scene, &camera, &mut accum, |tile, accum| {
+            for p in tile.pixels(width) {
+                buffer[p] = renderer::to_bgra_pixel(&scene.color, accum.pixels[p].mean(), p);
+            }
+            if last_frame.elapsed() >= FRAME_INTERVAL {
+                last_frame = Instant::now();
+                window.set_title(&progress.title(accum, &scene.settings));
+                window.update_with_buffer(&buffer).unwrap();
+            }
+            window.is_open() && !window.is_key_down(Key::Escape)
+        });
+        buffer = renderer::to_bgra_buffer(&scene.color, &accum.image());
+        window.set_title(&progress.title(&accum, &scene.settings));
+        // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
+        window.update_with_buffer(&buffer).unwrap();
+        if !completed {
+            break;
+        }
+        checkpointer.tick(&scene.settings, &accum);
+    }
+    checkpointer.save(&scene.settings, &accum);
 
-    let buffer = renderer::render(WIDTH, HEIGHT, camera, world);
+    // stopping early still saves the passes finished so far
+    if accum.total_samples > 0 {
+        opt.save(&accum, &scene.color);
+    }
+    window.set_title(&format!("raytrace - {} passes in {} - ESC to exit",
+                              accum.passes, format_duration(progress.start.elapsed())));
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
-        window.update_with_buffer(&buffer).unwrap();
+        window.update();
     }
 }
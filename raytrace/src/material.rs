@@ -1,47 +1,104 @@
-use rand::prelude::*;
-
 use crate::{
     vec3::{Vec3},
     ray::{Ray},
+    sampler::{Sampler},
     hitable::{HitRecord},
+    texture::{Texture},
+    color::{luminance},
+    spectrum::{self, Wavelength, WAVELENGTH_D},
 };
 
 pub struct Scatter {
+    // bsdf times cosine over pdf
     pub attenuation: Vec3,
     pub ray: Ray,
+    // solid angle density the direction was sampled with, None for specular
+    // scattering which light sampling can't reach
+    pub pdf: Option<f32>,
+    // the wavelength a spectral path picked here, the rgb light arriving along
+    // `ray` only counts at that wavelength, see `spectrum::project`
+    pub wavelength: Option<f32>,
 }
 
 impl Scatter {
     pub fn new(attenuation: Vec3, ray: Ray) -> Scatter {
-        Scatter { attenuation, ray }
+        Scatter { attenuation, ray, pdf: None, wavelength: None }
+    }
+
+    pub fn with_pdf(attenuation: Vec3, ray: Ray, pdf: f32) -> Scatter {
+        Scatter { attenuation, ray, pdf: Some(pdf), wavelength: None }
     }
 }
 
-#[derive(Copy, Clone)]
+// how light leaves a surface or medium, shared between objects behind an `Arc`
+pub trait Material: Send + Sync {
+    // picks the direction light arriving along `ray` continues in, None when it
+    // is absorbed
+    fn scatter(&self, ray: Ray, hit: HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter>;
+
+    fn emitted(&self, _hit: HitRecord) -> Vec3 {
+        Vec3::zeros()
+    }
+
+    // bsdf times cosine for light arriving from `direction`, zero for specular materials
+    fn eval(&self, _ray: Ray, _hit: HitRecord, _direction: Vec3) -> Vec3 {
+        Vec3::zeros()
+    }
+
+    // density `scatter` samples `direction` with, zero for specular materials
+    fn pdf(&self, _ray: Ray, _hit: HitRecord, _direction: Vec3) -> f32 {
+        0.0
+    }
+}
+
+#[derive(Clone)]
 pub struct Lambertian {
-    pub albedo: Vec3,
+    pub albedo: Texture,
 }
 
 impl Lambertian {
-    pub fn scatter(&self, _ray: Ray, hit: HitRecord, rng: &mut ThreadRng) -> Option<Scatter> {
-        let target = hit.p + hit.normal + random_in_unit_sphere(rng);
-        let scattered = Ray::new(hit.p, target - hit.p);
-        let attenuation = self.albedo;
-        Some(Scatter::new(attenuation, scattered))
+    pub fn new<T: Into<Texture>>(albedo: T) -> Lambertian {
+        Lambertian { albedo: albedo.into() }
     }
 }
 
-#[derive(Copy, Clone)]
+impl Material for Lambertian {
+    // cosine weighted
+    fn scatter(&self, ray: Ray, hit: HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let direction = random_cosine_direction(hit.normal, sampler.next_2d());
+        let scattered = hit.spawn_ray(direction);
+        let attenuation = self.albedo.value(hit.u, hit.v, hit.p);
+        Some(Scatter::with_pdf(attenuation, scattered, self.pdf(ray, hit, direction)))
+    }
+
+    fn eval(&self, ray: Ray, hit: HitRecord, direction: Vec3) -> Vec3 {
+        self.albedo.value(hit.u, hit.v, hit.p) * self.pdf(ray, hit, direction)
+    }
+
+    fn pdf(&self, _ray: Ray, hit: HitRecord, direction: Vec3) -> f32 {
+        let cosine = hit.normal.dot(direction.make_unit_vector());
+        if cosine > 0.0 { cosine / std::f32::consts::PI } else { 0.0 }
+    }
+}
+
+#[derive(Clone)]
 pub struct Metal {
-    pub albedo: Vec3,
+    pub albedo: Texture,
     pub fuzz: f32,
 }
 
 impl Metal {
-    pub fn scatter(&self, ray: Ray, hit: HitRecord, rng: &mut ThreadRng) -> Option<Scatter> {
+    pub fn new<T: Into<Texture>>(albedo: T, fuzz: f32) -> Metal {
+        Metal { albedo: albedo.into(), fuzz }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(&self, ray: Ray, hit: HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
         let reflected = ray.direction.make_unit_vector().reflect(hit.normal);
-        let scattered = Ray::new(hit.p, reflected + random_in_unit_sphere(rng) * self.fuzz);
-        let attenuation = self.albedo;
+        let fuzz = random_in_unit_sphere(sampler.next_2d(), sampler.next_1d());
+        let scattered = hit.spawn_ray(reflected + fuzz * self.fuzz);
+        let attenuation = self.albedo.value(hit.u, hit.v, hit.p);
         if scattered.direction.dot(hit.normal) > 0.0 {
             return Some(Scatter::new(attenuation, scattered));
         }
@@ -66,69 +123,339 @@ pub fn schlick(cosine: f32, ref_idx: f32) -> f32 {
     r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
 }
 
+// index of refraction, possibly varying with the wavelength
+#[derive(Copy, Clone, Debug)]
+pub enum Ior {
+    Constant(f32),
+    // n = a + b / λ², λ in micrometres
+    Cauchy { a: f32, b: f32 },
+    // n² = 1 + Σ b λ² / (λ² - c), λ in micrometres
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Ior {
+    // rays without a wavelength see the index at the sodium d line
+    pub fn at(&self, wavelength: Option<f32>) -> f32 {
+        let l = wavelength.unwrap_or(WAVELENGTH_D) * 1e-3;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / (l * l),
+            Ior::Sellmeier { b, c } => {
+                let l2 = l * l;
+                (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f32>()).sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Dielectric {
-    pub ref_idx: f32,
+    pub ior: Ior,
+    // of the light travelling inside, per unit of distance and channel, zero for
+    // clear glass
+    pub absorption: Vec3,
 }
 
 impl Dielectric {
-    pub fn scatter(&self, ray: Ray, hit: HitRecord, rng: &mut ThreadRng) -> Option<Scatter> {
-        let reflected = ray.direction.reflect(hit.normal);
+    pub fn new(ref_idx: f32) -> Dielectric {
+        Dielectric::with_ior(Ior::Constant(ref_idx))
+    }
+
+    // dispersive indices split white light into its colors in spectral mode
+    pub fn with_ior(ior: Ior) -> Dielectric {
+        Dielectric { ior, absorption: Vec3::zeros() }
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, ray: Ray, mut hit: HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
         let attenuation = Vec3::ones();
-        let (outward_normal, ni_over_nt, cosine) = if ray.direction.dot(hit.normal) > 0.0 {
-            (-hit.normal, self.ref_idx,
-             self.ref_idx * ray.direction.dot(hit.normal) / ray.direction.length())
+        // the first dispersive surface a spectral path meets picks the wavelength
+        // it carries from then on
+        let picked = if self.ior.is_dispersive() && hit.wavelength == Wavelength::Pending {
+            let wavelength = spectrum::sample_wavelength(sampler.next_1d());
+            hit.wavelength = Wavelength::Sampled(wavelength);
+            Some(wavelength)
         } else {
-            (hit.normal, 1.0 / self.ref_idx,
-             -ray.direction.dot(hit.normal) / ray.direction.length())
+            None
         };
-        if let Some(refracted) = refract(ray.direction, outward_normal, ni_over_nt) {
-            let reflection_prob = schlick(cosine, self.ref_idx);
-            if rng.gen::<f32>() < reflection_prob {
-                Some(Scatter::new(attenuation, Ray::new(hit.p, reflected)))
-            } else {
-                Some(Scatter::new(attenuation, Ray::new(hit.p, refracted)))
-            }
+        let ref_idx = self.ior.at(hit.wavelength.nanometres());
+        let reflected = ray.direction.reflect(hit.normal);
+        let (ni_over_nt, cosine) = if hit.front_face {
+            (1.0 / ref_idx, -ray.direction.dot(hit.normal) / ray.direction.length())
         } else {
-            Some(Scatter::new(attenuation, Ray::new(hit.p, reflected)))
-        }
+            (ref_idx, -ref_idx * ray.direction.dot(hit.normal) / ray.direction.length())
+        };
+        let scattered = match refract(ray.direction, hit.normal, ni_over_nt) {
+            // refracted rays travel through the glass when they enter it and through
+            // the open when they leave, nested glass is not tracked
+            Some(refracted) if sampler.next_1d() >= schlick(cosine, ref_idx) => Ray {
+                absorption: if hit.front_face { self.absorption } else { Vec3::zeros() },
+                ..hit.spawn_ray(refracted)
+            },
+            _ => hit.spawn_ray(reflected),
+        };
+        Some(Scatter { wavelength: picked, ..Scatter::new(attenuation, scattered) })
     }
 }
 
+// emits light, from both sides of the surface, and scatters none
 #[derive(Copy, Clone)]
-pub enum Material {
-    Lambertian(Lambertian),
-    Metal(Metal),
-    Dielectric(Dielectric),
+pub struct DiffuseLight {
+    pub emit: Vec3,
 }
 
-impl Material {
-    pub fn lambertian(albedo: Vec3) -> Material {
-        Material::Lambertian(Lambertian { albedo })
+impl DiffuseLight {
+    pub fn new(emit: Vec3) -> DiffuseLight {
+        DiffuseLight { emit }
     }
+}
 
-    pub fn metal(albedo: Vec3, fuzz: f32) -> Material {
-        Material::Metal(Metal { albedo, fuzz })
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray: Ray, _hit: HitRecord, _sampler: &mut dyn Sampler) -> Option<Scatter> {
+        None
     }
 
-    pub fn dielectric(ref_idx: f32) -> Material {
-        Material::Dielectric(Dielectric { ref_idx })
+    fn emitted(&self, _hit: HitRecord) -> Vec3 {
+        self.emit
     }
+}
 
-    pub fn scatter(&self, ray: Ray, hit: HitRecord, rng: &mut ThreadRng) -> Option<Scatter> {
-        match hit.material {
-            Material::Lambertian(l) => l.scatter(ray, hit, rng),
-            Material::Metal(m) => m.scatter(ray, hit, rng),
-            Material::Dielectric(d) => d.scatter(ray, hit, rng),
-        }
+// how much light a microfacet reflects rather than lets in
+#[derive(Copy, Clone)]
+pub enum Fresnel {
+    // from the reflectance at normal incidence, the albedo for metals
+    Schlick,
+    // exact for a metal with the complex index of refraction eta + ik per channel
+    Conductor { eta: Vec3, k: Vec3 },
+}
+
+// of dielectrics such as plastic, reflects 4% at normal incidence
+const DIELECTRIC_IOR: f32 = 1.5;
+
+// rough surfaces of tiny mirrors facing along the ggx (trowbridge-reitz)
+// distribution, with smith masking-shadowing, over a diffuse base where not metallic
+#[derive(Clone)]
+pub struct Microfacet {
+    pub albedo: Texture,
+    // perceptual between 0 and 1, the ggx alpha is its square
+    pub roughness: f32,
+    // 0 for dielectrics, 1 for metals, in between for blends
+    pub metallic: f32,
+    pub fresnel: Fresnel,
+}
+
+impl Microfacet {
+    pub fn new<T: Into<Texture>>(albedo: T, roughness: f32, metallic: f32, fresnel: Fresnel) -> Microfacet {
+        Microfacet { albedo: albedo.into(), roughness, metallic, fresnel }
+    }
+
+    fn alpha(&self) -> f32 {
+        // keeps the distribution from turning into a delta
+        (self.roughness * self.roughness).max(1e-3)
+    }
+
+    fn fresnel(&self, albedo: Vec3, cos_theta: f32) -> Vec3 {
+        let metal = match self.fresnel {
+            Fresnel::Schlick => schlick_color(albedo, cos_theta),
+            Fresnel::Conductor { eta, k } => Vec3::new(fresnel_conductor(cos_theta, eta.x, k.x),
+                                                       fresnel_conductor(cos_theta, eta.y, k.y),
+                                                       fresnel_conductor(cos_theta, eta.z, k.z)),
+        };
+        let dielectric = schlick(cos_theta, DIELECTRIC_IOR);
+        metal * self.metallic + Vec3::ones() * (dielectric * (1.0 - self.metallic))
+    }
+
+    // the weights of the diffuse lobe and the chance of sampling the specular one
+    fn lobes(&self, albedo: Vec3, cos_o: f32) -> (Vec3, f32) {
+        let diffuse = albedo * ((1.0 - self.metallic) * (1.0 - schlick(cos_o, DIELECTRIC_IOR)));
+        let specular = luminance(self.fresnel(albedo, cos_o));
+        let d = luminance(diffuse);
+        let p_specular = if d <= 0.0 { 1.0 } else { (specular / (specular + d)).clamp(0.1, 0.9) };
+        (diffuse, p_specular)
     }
 }
 
-fn random_in_unit_sphere(rng: &mut ThreadRng) -> Vec3 {
-    loop {
-        let p = Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()) * 2.0 - Vec3::ones();
-        if p.squared_length() < 1.0 {
-            break p;
+impl Material for Microfacet {
+    fn scatter(&self, ray: Ray, hit: HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let wo = -ray.direction.make_unit_vector();
+        let albedo = self.albedo.value(hit.u, hit.v, hit.p);
+        let (_, p_specular) = self.lobes(albedo, wo.dot(hit.normal));
+        let u = sampler.next_2d();
+        let direction = if sampler.next_1d() < p_specular {
+            let (t, b) = hit.normal.basis();
+            let local_o = Vec3::new(wo.dot(t), wo.dot(b), wo.dot(hit.normal));
+            let h = sample_ggx_visible(local_o, self.alpha(), u);
+            let h = t * h.x + b * h.y + hit.normal * h.z;
+            (-wo).reflect(h)
+        } else {
+            random_cosine_direction(hit.normal, u)
+        };
+        let pdf = self.pdf(ray, hit, direction);
+        if direction.dot(hit.normal) <= 0.0 || pdf <= 0.0 {
+            return None;
+        }
+        Some(Scatter::with_pdf(self.eval(ray, hit, direction) / pdf, hit.spawn_ray(direction), pdf))
+    }
+
+    fn eval(&self, ray: Ray, hit: HitRecord, direction: Vec3) -> Vec3 {
+        let (wo, wi, n) = (-ray.direction.make_unit_vector(), direction.make_unit_vector(), hit.normal);
+        let (cos_o, cos_i) = (wo.dot(n), wi.dot(n));
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return Vec3::zeros();
+        }
+        let albedo = self.albedo.value(hit.u, hit.v, hit.p);
+        let h = (wo + wi).make_unit_vector();
+        let alpha = self.alpha();
+        let g = 1.0 / (1.0 + smith_lambda(cos_o, alpha) + smith_lambda(cos_i, alpha));
+        let specular = self.fresnel(albedo, wo.dot(h)) * (ggx(h.dot(n), alpha) * g / (4.0 * cos_o));
+        let (diffuse, _) = self.lobes(albedo, cos_o);
+        specular + diffuse * (cos_i / std::f32::consts::PI)
+    }
+
+    fn pdf(&self, ray: Ray, hit: HitRecord, direction: Vec3) -> f32 {
+        let (wo, wi, n) = (-ray.direction.make_unit_vector(), direction.make_unit_vector(), hit.normal);
+        let (cos_o, cos_i) = (wo.dot(n), wi.dot(n));
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return 0.0;
         }
+        let albedo = self.albedo.value(hit.u, hit.v, hit.p);
+        let (_, p_specular) = self.lobes(albedo, cos_o);
+        let h = (wo + wi).make_unit_vector();
+        let alpha = self.alpha();
+        // visible normals, turned into the density of the reflected direction
+        let specular = ggx(h.dot(n), alpha) / (1.0 + smith_lambda(cos_o, alpha)) / (4.0 * cos_o);
+        p_specular * specular + (1.0 - p_specular) * cos_i / std::f32::consts::PI
+    }
+}
+
+// density of microfacet normals at angle theta to the surface normal
+fn ggx(cos_theta: f32, alpha: f32) -> f32 {
+    if cos_theta <= 0.0 {
+        return 0.0;
     }
+    let a2 = alpha * alpha;
+    let d = cos_theta * cos_theta * (a2 - 1.0) + 1.0;
+    a2 / (std::f32::consts::PI * d * d)
+}
+
+// the smith g1 masking for a direction at theta to the normal is 1 / (1 + lambda)
+fn smith_lambda(cos_theta: f32, alpha: f32) -> f32 {
+    let cos2 = cos_theta * cos_theta;
+    let tan2 = (1.0 - cos2).max(0.0) / cos2;
+    0.5 * ((1.0 + alpha * alpha * tan2).sqrt() - 1.0)
+}
+
+// heitz's sampling of the normals visible from `wo`, both in the frame of the
+// surface normal along z
+fn sample_ggx_visible(wo: Vec3, alpha: f32, (u1, u2): (f32, f32)) -> Vec3 {
+    let vh = Vec3::new(alpha * wo.x, alpha * wo.y, wo.z).make_unit_vector();
+    let len_sq = vh.x * vh.x + vh.y * vh.y;
+    let t1 = if len_sq > 0.0 { Vec3::new(-vh.y, vh.x, 0.0) / len_sq.sqrt() } else { Vec3::new(1.0, 0.0, 0.0) };
+    let t2 = vh.cross(t1);
+    let r = u1.sqrt();
+    let phi = 2.0 * std::f32::consts::PI * u2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + vh.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+    let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+    Vec3::new(alpha * nh.x, alpha * nh.y, nh.z.max(1e-6)).make_unit_vector()
+}
+
+fn schlick_color(f0: Vec3, cosine: f32) -> Vec3 {
+    f0 + (Vec3::ones() - f0) * (1.0 - cosine).max(0.0).powi(5)
+}
+
+// unpolarized reflectance of a conductor
+fn fresnel_conductor(cos_theta: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+// scatters inside participating media, by the henyey-greenstein phase function
+#[derive(Clone)]
+pub struct Volume {
+    // chance of scattering rather than being absorbed
+    pub albedo: Texture,
+    // asymmetry in (-1, 1), positive scatters forwards, 0 is isotropic
+    pub g: f32,
+}
+
+impl Volume {
+    pub fn new<T: Into<Texture>>(albedo: T, g: f32) -> Volume {
+        Volume { albedo: albedo.into(), g }
+    }
+}
+
+impl Material for Volume {
+    fn scatter(&self, ray: Ray, hit: HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+        let w = ray.direction.make_unit_vector();
+        let cos_theta = sample_henyey_greenstein(self.g, sampler.next_1d());
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * sampler.next_1d();
+        let (u, v) = w.basis();
+        let direction = u * (sin_theta * phi.cos()) + v * (sin_theta * phi.sin()) + w * cos_theta;
+        let attenuation = self.albedo.value(hit.u, hit.v, hit.p);
+        Some(Scatter::with_pdf(attenuation, hit.spawn_ray(direction), henyey_greenstein(self.g, cos_theta)))
+    }
+
+    fn eval(&self, ray: Ray, hit: HitRecord, direction: Vec3) -> Vec3 {
+        self.albedo.value(hit.u, hit.v, hit.p) * self.pdf(ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: Ray, _hit: HitRecord, direction: Vec3) -> f32 {
+        let cos_theta = ray.direction.make_unit_vector().dot(direction.make_unit_vector());
+        henyey_greenstein(self.g, cos_theta)
+    }
+}
+
+// density over the sphere of directions at angle theta to the incoming direction
+fn henyey_greenstein(g: f32, cos_theta: f32) -> f32 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * std::f32::consts::PI * denom * denom.sqrt())
+}
+
+// cosine of the angle to the incoming direction
+fn sample_henyey_greenstein(g: f32, u: f32) -> f32 {
+    if g.abs() < 1e-3 {
+        return 1.0 - 2.0 * u;
+    }
+    let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+    ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+}
+
+// uniform on the unit sphere
+fn random_unit_vector((u1, u2): (f32, f32)) -> Vec3 {
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * std::f32::consts::PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// uniform in the unit ball
+fn random_in_unit_sphere(u: (f32, f32), u3: f32) -> Vec3 {
+    random_unit_vector(u) * u3.cbrt()
+}
+
+// cosine weighted about `normal`
+fn random_cosine_direction(normal: Vec3, (u1, u2): (f32, f32)) -> Vec3 {
+    let r = u1.sqrt();
+    let phi = 2.0 * std::f32::consts::PI * u2;
+    let (u, v) = normal.basis();
+    u * (r * phi.cos()) + v * (r * phi.sin()) + normal * (1.0 - u1).max(0.0).sqrt()
 }
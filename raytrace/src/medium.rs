@@ -0,0 +1,108 @@
+use crate::{
+    vec3::{Vec3},
+    ray::{Ray},
+    aabb::{Aabb},
+    hitable::{HitRecord, Hitable},
+    material::{Volume},
+};
+
+// scatters at points inside a closed boundary, at a constant rate per unit of
+// distance, by the material of the boundary, where the `scatter_depth` of the ray
+// runs out, media further along a ray that passed through one unscattered see the
+// same depth
+pub struct ConstantMedium {
+    pub boundary: Box<dyn Hitable>,
+    pub density: f32,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Box<dyn Hitable>, density: f32) -> ConstantMedium {
+        ConstantMedium { boundary, density }
+    }
+}
+
+impl Hitable for ConstantMedium {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        // entering and leaving the boundary, rays starting inside enter behind their origin
+        let enter = self.boundary.hit(ray, f32::MIN, f32::MAX)?;
+        let exit = self.boundary.hit(ray, enter.t + 0.0001, f32::MAX)?;
+        let (t0, t1) = (enter.t.max(t_min), exit.t.min(t_max));
+        if t0 >= t1 {
+            return None;
+        }
+        let speed = ray.direction.length();
+        let distance = ray.scatter_depth / (self.density * speed);
+        if distance >= t1 - t0 {
+            return None;
+        }
+        // the normal is arbitrary, volumes scatter the same in every orientation
+        Some(HitRecord::new(ray, t0 + distance, Vec3::new(1.0, 0.0, 0.0), enter.u, enter.v, enter.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+// a medium filling the space between the surfaces of the scene, rays that escape
+// see the background as if there were no fog
+pub struct Fog {
+    pub density: f32,
+    pub material: Volume,
+}
+
+impl Fog {
+    pub fn new(density: f32, material: Volume) -> Fog {
+        Fog { density, material }
+    }
+
+    // where along `ray` it scatters before reaching `t_max`, if it does
+    pub fn scatter_at(&self, ray: Ray, t_max: f32, u: f32) -> Option<HitRecord<'_>> {
+        let t = free_flight(self.density, u) / ray.direction.length();
+        if t >= t_max {
+            return None;
+        }
+        Some(HitRecord::new(ray, t, Vec3::new(1.0, 0.0, 0.0), 0.0, 0.0, &self.material))
+    }
+
+    // fraction of the light passing through `distance` of fog unscattered
+    pub fn transmittance(&self, distance: f32) -> f32 {
+        (-self.density * distance).exp()
+    }
+}
+
+// the number of mean free paths to the next collision, exponentially distributed
+pub fn scatter_depth(u: f32) -> f32 {
+    -(1.0 - u).ln()
+}
+
+// distance to the next collision in a homogeneous medium
+fn free_flight(density: f32, u: f32) -> f32 {
+    scatter_depth(u) / density
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::{hitable::{Sphere}, material::{Lambertian}};
+
+    #[test]
+    fn rays_scatter_at_the_rate_of_the_density() {
+        let boundary = Sphere::new(Vec3::zeros(), 1.0, Arc::new(Lambertian::new(Vec3::ones())));
+        let medium = ConstantMedium::new(Box::new(boundary), 0.5);
+        // through the center, two units of medium at half a collision per unit
+        let n = 10000;
+        let scattered = (0..n)
+            .filter(|&i| {
+                let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0), 0.0);
+                let ray = Ray { scatter_depth: scatter_depth((i as f32 + 0.5) / n as f32), ..ray };
+                medium.hit(ray, 0.0001, f32::MAX).is_some_and(|hit| hit.p.z.abs() <= 1.0)
+            })
+            .count();
+        let expected = 1.0 - (-1.0f32).exp();
+        assert!((scattered as f32 / n as f32 - expected).abs() < 1e-3, "{} of {} scattered", scattered, n);
+        let unsampled = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(medium.hit(unsampled, 0.0001, f32::MAX).is_none());
+    }
+}
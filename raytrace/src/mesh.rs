@@ -0,0 +1,92 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{
+    vec3::{Vec3},
+    ray::{Ray},
+    material::{Material},
+    aabb::{Aabb},
+    bvh::{Bvh},
+    hitable::{HitRecord, Hitable},
+    shapes::{intersect_triangle, THICKNESS},
+    obj, ply,
+};
+
+// indexed triangle mesh with its own bvh
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    // per vertex, empty for flat shading
+    pub normals: Vec<Vec3>,
+    // per vertex, may be empty
+    pub uvs: Vec<[f32; 2]>,
+    pub triangles: Vec<[u32; 3]>,
+    pub material: Arc<dyn Material>,
+    bvh: Bvh,
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Vec3>, normals: Vec<Vec3>, uvs: Vec<[f32; 2]>,
+               triangles: Vec<[u32; 3]>, material: Arc<dyn Material>) -> Mesh {
+        // padded like single triangles, axis aligned ones would get flat boxes
+        let pad = Vec3::new(THICKNESS, THICKNESS, THICKNESS);
+        let boxes: Vec<Aabb> = triangles
+            .iter()
+            .map(|tri| {
+                let [p0, p1, p2] = tri.map(|i| positions[i as usize]);
+                Aabb::new(p0.min(p1).min(p2) - pad, p0.max(p1).max(p2) + pad)
+            })
+            .collect();
+        let (bvh, order) = Bvh::new(&boxes);
+        let triangles = order.iter().map(|&i| triangles[i]).collect();
+        Mesh { positions, normals, uvs, triangles, material, bvh }
+    }
+
+    // loads a wavefront obj or ply file, chosen by extension
+    pub fn load(path: &Path, material: Arc<dyn Material>) -> io::Result<Mesh> {
+        let ext = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        match ext.as_deref() {
+            Some("obj") => obj::load(path, material),
+            Some("ply") => ply::load(path, material),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                    format!("unsupported mesh format: {}", path.display()))),
+        }
+    }
+
+    fn hit_triangle(&self, index: usize, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let [i0, i1, i2] = self.triangles[index].map(|i| i as usize);
+        let (p0, p1, p2) = (self.positions[i0], self.positions[i1], self.positions[i2]);
+        let (t, b1, b2) = intersect_triangle(ray, p0, p1, p2, t_min, t_max)?;
+        let b0 = 1.0 - b1 - b2;
+        let (u, v) = if self.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let (uv0, uv1, uv2) = (self.uvs[i0], self.uvs[i1], self.uvs[i2]);
+            (uv0[0] * b0 + uv1[0] * b1 + uv2[0] * b2,
+             uv0[1] * b0 + uv1[1] * b1 + uv2[1] * b2)
+        };
+        let geometric_normal = (p1 - p0).cross(p2 - p0).make_unit_vector();
+        let mut hit = HitRecord::new(ray, t, geometric_normal, u, v, &*self.material);
+        if !self.normals.is_empty() {
+            let n = (self.normals[i0] * b0 + self.normals[i1] * b1 + self.normals[i2] * b2).make_unit_vector();
+            hit.normal = if hit.front_face { n } else { -n };
+        }
+        Some(hit)
+    }
+}
+
+impl Hitable for Mesh {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.bvh.hit(ray, t_min, t_max, |i, t_max| self.hit_triangle(i, ray, t_min, t_max))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+}
+
+pub fn invalid_data(line: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, message))
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{
+    vec3::{Vec3},
+    material::{Material},
+    mesh::{Mesh, invalid_data},
+};
+
+// wavefront obj, polygons are fan triangulated and materials from mtl files are ignored
+pub fn load(path: &Path, material: Arc<dyn Material>) -> io::Result<Mesh> {
+    read(BufReader::new(File::open(path)?), material)
+}
+
+pub fn read<R: BufRead>(reader: R, material: Arc<dyn Material>) -> io::Result<Mesh> {
+    let mut obj_positions: Vec<Vec3> = Vec::new();
+    let mut obj_uvs: Vec<[f32; 2]> = Vec::new();
+    let mut obj_normals: Vec<Vec3> = Vec::new();
+
+    // obj indexes positions, uvs and normals separately, the mesh uses one index per vertex
+    let mut vertices: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+    let mut has_uvs = true;
+    let mut has_normals = true;
+    let mut triangles = Vec::new();
+
+    for (line_index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_no = line_index + 1;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let [x, y, z] = parse_floats(&mut tokens, line_no)?;
+                obj_positions.push(Vec3::new(x, y, z));
+            }
+            Some("vt") => {
+                let [u, v] = parse_floats(&mut tokens, line_no)?;
+                obj_uvs.push([u, v]);
+            }
+            Some("vn") => {
+                let [x, y, z] = parse_floats(&mut tokens, line_no)?;
+                obj_normals.push(Vec3::new(x, y, z));
+            }
+            Some("f") => {
+                let mut face = Vec::new();
+                for token in tokens {
+                    let mut parts = token.split('/');
+                    let p = resolve(parts.next(), obj_positions.len(), line_no)?
+                        .ok_or_else(|| invalid_data(line_no, "face vertex without a position"))?;
+                    let t = resolve(parts.next(), obj_uvs.len(), line_no)?;
+                    let n = resolve(parts.next(), obj_normals.len(), line_no)?;
+                    let index = *vertices.entry((p, t, n)).or_insert_with(|| {
+                        positions.push(obj_positions[p]);
+                        match t {
+                            Some(t) => uvs.push(obj_uvs[t]),
+                            None => has_uvs = false,
+                        }
+                        match n {
+                            Some(n) => normals.push(obj_normals[n]),
+                            None => has_normals = false,
+                        }
+                        (positions.len() - 1) as u32
+                    });
+                    face.push(index);
+                }
+                if face.len() < 3 {
+                    return Err(invalid_data(line_no, "face with fewer than 3 vertices"));
+                }
+                for i in 1..face.len() - 1 {
+                    triangles.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !has_uvs {
+        uvs.clear();
+    }
+    if !has_normals {
+        normals.clear();
+    }
+    Ok(Mesh::new(positions, normals, uvs, triangles, material))
+}
+
+fn parse_floats<'a, I, const N: usize>(tokens: &mut I, line_no: usize) -> io::Result<[f32; N]>
+    where I: Iterator<Item = &'a str>
+{
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        *value = tokens
+            .next()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| invalid_data(line_no, "expected a number"))?;
+    }
+    Ok(values)
+}
+
+// obj indices are one based, negative indices count back from the last element
+fn resolve(token: Option<&str>, count: usize, line_no: usize) -> io::Result<Option<usize>> {
+    let token = match token {
+        Some(t) if !t.is_empty() => t,
+        _ => return Ok(None),
+    };
+    let index: i64 = token.parse().map_err(|_| invalid_data(line_no, "invalid index"))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(invalid_data(line_no, "index out of range"));
+    }
+    Ok(Some(resolved as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ray::{Ray},
+        hitable::{Hitable},
+        material::{Lambertian},
+    };
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::ones()))
+    }
+
+    #[test]
+    fn flat_quad_is_hit() {
+        let quad = "v -1 0 -1\nv 1 0 -1\nv 1 0 1\nv -1 0 1\nf 1 2 3 4\n";
+        let mesh = read(quad.as_bytes(), material()).unwrap();
+        for &(x, z) in &[(0.0, 0.0), (0.5, -0.5), (-0.9, 0.9)] {
+            let ray = Ray::new(Vec3::new(x, 1.0, z), Vec3::new(0.0, -1.0, 0.0), 0.0);
+            let hit = mesh.hit(ray, 0.001, f32::MAX).expect("ray missed the quad");
+            assert!((hit.t - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn polygons_are_fan_triangulated() {
+        let pentagon = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0.5 2 0\nv 0 1 0\nf 1 2 3 4 5\n";
+        let mesh = read(pentagon.as_bytes(), material()).unwrap();
+        assert_eq!(mesh.positions.len(), 5);
+        let mut triangles = mesh.triangles.clone();
+        triangles.sort();
+        assert_eq!(triangles, vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+        assert!(mesh.uvs.is_empty());
+        assert!(mesh.normals.is_empty());
+    }
+
+    #[test]
+    fn negative_indices_count_from_the_end() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 1\n\
+                   f -3/-3/-1 -2/-2/-1 -1/-1/-1\n";
+        let mesh = read(obj.as_bytes(), material()).unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        assert_eq!(mesh.uvs, vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+        assert_eq!(mesh.normals.len(), 3);
+        assert!(mesh.normals.iter().all(|n| n.z == 1.0));
+    }
+
+    #[test]
+    fn vertices_are_shared_between_faces() {
+        let quad = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3\nf 1 3 4\n";
+        let mesh = read(quad.as_bytes(), material()).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.triangles.len(), 2);
+    }
+
+    fn error(obj: &str) -> String {
+        match read(obj.as_bytes(), material()) {
+            Ok(_) => panic!("expected {:?} to fail", obj),
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+                e.to_string()
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_files_report_the_line() {
+        assert_eq!(error("v 0 0 0\nf 1 2 3\n"), "line 2: index out of range");
+        assert_eq!(error("v 0 0 0\nv 1 0 0\nf 1 2\n"), "line 3: face with fewer than 3 vertices");
+        assert_eq!(error("v 0 zero 0\n"), "line 1: expected a number");
+        assert_eq!(error("v 0 0 0\nf 1 x 1\n"), "line 2: invalid index");
+    }
+}
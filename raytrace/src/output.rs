@@ -0,0 +1,55 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::{
+    vec3::{Vec3},
+    color::{ColorSettings},
+};
+
+// pixels are linear radiance, top row first, `color` turns them into 8 bit color
+// for the formats that need it
+pub fn save(path: &Path, width: usize, height: usize, pixels: &[Vec3], color: &ColorSettings) -> io::Result<()> {
+    let ext = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    match ext.as_deref() {
+        Some("ppm") => write_ppm(path, width, height, &to_rgb8_buffer(pixels, color)),
+        Some("png") => write_png(path, width, height, &to_rgb8_buffer(pixels, color)),
+        Some("pfm") => write_pfm(path, width, height, pixels),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                format!("unsupported output format: {}", path.display()))),
+    }
+}
+
+fn to_rgb8_buffer(pixels: &[Vec3], color: &ColorSettings) -> Vec<u8> {
+    pixels.iter().enumerate().flat_map(|(p, col)| color.to_rgb8(*col, p).to_vec()).collect()
+}
+
+pub fn write_ppm(path: &Path, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "P6\n{} {}\n255\n", width, height)?;
+    file.write_all(rgb)?;
+    file.flush()
+}
+
+pub fn write_png(path: &Path, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    image::save_buffer(path, rgb, width as u32, height as u32, image::ColorType::Rgb8)
+        .map_err(io::Error::other)
+}
+
+// portable float map, keeps the full HDR range
+pub fn write_pfm(path: &Path, width: usize, height: usize, pixels: &[Vec3]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    // a negative scale marks the data as little endian
+    write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+    // pfm scanlines go from bottom to top
+    for row in pixels.chunks(width).rev() {
+        for col in row {
+            for c in &[col.x, col.y, col.z] {
+                file.write_all(&c.to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()
+}
@@ -0,0 +1,302 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{
+    vec3::{Vec3},
+    material::{Material},
+    mesh::{Mesh, invalid_data},
+};
+
+#[derive(Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone)]
+enum Type {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl Type {
+    fn parse(name: &str) -> Option<Type> {
+        match name {
+            "char" | "int8" => Some(Type::Int8),
+            "uchar" | "uint8" => Some(Type::Uint8),
+            "short" | "int16" => Some(Type::Int16),
+            "ushort" | "uint16" => Some(Type::Uint16),
+            "int" | "int32" => Some(Type::Int32),
+            "uint" | "uint32" => Some(Type::Uint32),
+            "float" | "float32" => Some(Type::Float32),
+            "double" | "float64" => Some(Type::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Type::Int8 | Type::Uint8 => 1,
+            Type::Int16 | Type::Uint16 => 2,
+            Type::Int32 | Type::Uint32 | Type::Float32 => 4,
+            Type::Float64 => 8,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Type),
+    List(String, Type, Type),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// values of the body in file order
+struct Body<'a> {
+    format: Format,
+    bytes: &'a [u8],
+    tokens: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, ty: Type) -> io::Result<f64> {
+        let unexpected_end = || io::Error::new(io::ErrorKind::UnexpectedEof, "ply body ended early");
+        if self.format == Format::Ascii {
+            let token = self.tokens.next().ok_or_else(unexpected_end)?;
+            return token.parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("invalid ply value: {}", token))
+            });
+        }
+        if self.bytes.len() < ty.size() {
+            return Err(unexpected_end());
+        }
+        let (value, rest) = self.bytes.split_at(ty.size());
+        self.bytes = rest;
+        let little = self.format == Format::BinaryLittleEndian;
+        macro_rules! decode {
+            ($t:ty) => {{
+                let b = value.try_into().unwrap();
+                (if little { <$t>::from_le_bytes(b) } else { <$t>::from_be_bytes(b) }) as f64
+            }};
+        }
+        Ok(match ty {
+            Type::Int8 => decode!(i8),
+            Type::Uint8 => decode!(u8),
+            Type::Int16 => decode!(i16),
+            Type::Uint16 => decode!(u16),
+            Type::Int32 => decode!(i32),
+            Type::Uint32 => decode!(u32),
+            Type::Float32 => decode!(f32),
+            Type::Float64 => decode!(f64),
+        })
+    }
+}
+
+// ascii and binary ply, reads the vertex (position, normal, uv) and face elements
+pub fn load(path: &Path, material: Arc<dyn Material>) -> io::Result<Mesh> {
+    read(BufReader::new(File::open(path)?), material)
+}
+
+pub fn read<R: BufRead>(mut reader: R, material: Arc<dyn Material>) -> io::Result<Mesh> {
+    let (format, elements) = read_header(&mut reader)?;
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let text = if format == Format::Ascii {
+        std::str::from_utf8(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    } else {
+        ""
+    };
+    let mut body = Body { format, bytes: &data, tokens: text.split_ascii_whitespace() };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut triangles = Vec::new();
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut position = [0.0f32; 3];
+            let mut normal = [0.0f32; 3];
+            let mut uv = [0.0f32; 2];
+            let mut face = Vec::new();
+            for property in &element.properties {
+                match property {
+                    Property::Scalar(name, ty) => {
+                        let value = body.read(*ty)? as f32;
+                        match name.as_str() {
+                            "x" => position[0] = value,
+                            "y" => position[1] = value,
+                            "z" => position[2] = value,
+                            "nx" => normal[0] = value,
+                            "ny" => normal[1] = value,
+                            "nz" => normal[2] = value,
+                            "u" | "s" | "texture_u" | "texture_s" => uv[0] = value,
+                            "v" | "t" | "texture_v" | "texture_t" => uv[1] = value,
+                            _ => {}
+                        }
+                    }
+                    Property::List(name, count_type, item_type) => {
+                        let count = body.read(*count_type)? as usize;
+                        for _ in 0..count {
+                            let value = body.read(*item_type)?;
+                            if name == "vertex_indices" || name == "vertex_index" {
+                                face.push(value as u32);
+                            }
+                        }
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                positions.push(Vec3::new(position[0], position[1], position[2]));
+                normals.push(Vec3::new(normal[0], normal[1], normal[2]));
+                uvs.push(uv);
+            } else if element.name == "face" && face.len() >= 3 {
+                for i in 1..face.len() - 1 {
+                    triangles.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+        }
+    }
+
+    let vertex_properties: Vec<&str> = elements
+        .iter()
+        .filter(|e| e.name == "vertex")
+        .flat_map(|e| &e.properties)
+        .filter_map(|p| match p {
+            Property::Scalar(name, _) => Some(name.as_str()),
+            Property::List(..) => None,
+        })
+        .collect();
+    if !vertex_properties.contains(&"nx") {
+        normals.clear();
+    }
+    if !vertex_properties.iter().any(|p| ["u", "s", "texture_u", "texture_s"].contains(p)) {
+        uvs.clear();
+    }
+    if triangles.iter().flatten().any(|&i| i as usize >= positions.len()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "ply face index out of range"));
+    }
+    Ok(Mesh::new(positions, normals, uvs, triangles, material))
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> io::Result<(Format, Vec<Element>)> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut line = String::new();
+    let mut line_no = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data(line_no, "ply header without end_header"));
+        }
+        line_no += 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens[..] {
+            ["ply"] if line_no == 1 => {}
+            _ if line_no == 1 => return Err(invalid_data(line_no, "not a ply file")),
+            ["format", name, _version] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(invalid_data(line_no, "unknown ply format")),
+                });
+            }
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| invalid_data(line_no, "invalid element count"))?;
+                elements.push(Element { name: name.to_string(), count, properties: Vec::new() });
+            }
+            ["property", "list", count_type, item_type, name] => {
+                let property = match (Type::parse(count_type), Type::parse(item_type)) {
+                    (Some(c), Some(i)) => Property::List(name.to_string(), c, i),
+                    _ => return Err(invalid_data(line_no, "unknown property type")),
+                };
+                elements.last_mut()
+                    .ok_or_else(|| invalid_data(line_no, "property outside of an element"))?
+                    .properties.push(property);
+            }
+            ["property", ty, name] => {
+                let ty = Type::parse(ty).ok_or_else(|| invalid_data(line_no, "unknown property type"))?;
+                elements.last_mut()
+                    .ok_or_else(|| invalid_data(line_no, "property outside of an element"))?
+                    .properties.push(Property::Scalar(name.to_string(), ty));
+            }
+            ["end_header"] => break,
+            _ => {}
+        }
+    }
+    let format = format.ok_or_else(|| invalid_data(line_no, "ply header without format"))?;
+    Ok((format, elements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{Lambertian},
+    };
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Vec3::ones()))
+    }
+
+    fn header(format: &str, vertices: usize, faces: usize) -> String {
+        format!("ply\nformat {} 1.0\nelement vertex {}\nproperty float x\nproperty float y\nproperty float z\n\
+                 element face {}\nproperty list uchar int vertex_indices\nend_header\n",
+                format, vertices, faces)
+    }
+
+    #[test]
+    fn ascii_quad() {
+        let ply = header("ascii", 4, 1) + "0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n";
+        let mesh = read(ply.as_bytes(), material()).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.positions[2], Vec3::new(1.0, 1.0, 0.0));
+        let mut triangles = mesh.triangles.clone();
+        triangles.sort();
+        assert_eq!(triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert!(mesh.normals.is_empty());
+        assert!(mesh.uvs.is_empty());
+    }
+
+    #[test]
+    fn binary_matches_ascii() {
+        let positions = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        for &(format, big_endian) in &[("binary_little_endian", false), ("binary_big_endian", true)] {
+            let mut ply = header(format, 3, 1).into_bytes();
+            for value in positions.iter().flatten() {
+                ply.extend_from_slice(&if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+            }
+            ply.push(3);
+            for index in 0..3i32 {
+                ply.extend_from_slice(&if big_endian { index.to_be_bytes() } else { index.to_le_bytes() });
+            }
+            let mesh = read(&ply[..], material()).unwrap();
+            assert_eq!(mesh.positions, positions.iter().map(|&p| Vec3::from(p)).collect::<Vec<_>>());
+            assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        }
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        let missing_end = "ply\nformat ascii 1.0\nelement vertex 0\n";
+        assert!(read(missing_end.as_bytes(), material()).is_err());
+        assert!(read("obj\n".as_bytes(), material()).is_err());
+        let out_of_range = header("ascii", 3, 1) + "0 0 0\n1 0 0\n0 1 0\n3 0 1 5\n";
+        let e = read(out_of_range.as_bytes(), material()).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
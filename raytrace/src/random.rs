@@ -0,0 +1,23 @@
+use rand_pcg::Pcg32;
+
+// the generator every random decision while rendering is made with
+pub type SampleRng = Pcg32;
+
+// an independent stream for each sample of each pixel, so a render only depends
+// on the seed and not on how the pixels were spread over threads
+pub fn sample_rng(seed: u64, pixel: usize, sample: u32) -> SampleRng {
+    Pcg32::new(hash(seed, pixel as u64, sample as u64), 0x0a02_bdbf_7bb3_c0a7)
+}
+
+// well mixed bits from three values
+pub fn hash(seed: u64, a: u64, b: u64) -> u64 {
+    mix(mix(seed ^ mix(a)) ^ b)
+}
+
+// splitmix64 finalizer
+fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
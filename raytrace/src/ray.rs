@@ -1,19 +1,56 @@
 use crate::{
     vec3::{Vec3},
+    spectrum::{Wavelength},
 };
 
 #[derive(Copy, Clone, Debug)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    // the moment within the shutter interval the ray samples the scene at
+    pub time: f32,
+    pub wavelength: Wavelength,
+    // per unit of distance by the medium the ray travels through, zero in the open
+    pub absorption: Vec3,
+    // how deep into media the ray gets before it scatters, in mean free paths,
+    // infinite unless the renderer drew one for it
+    pub scatter_depth: f32,
 }
 
 impl Ray {
-    pub fn new(origin: Vec3, direction: Vec3) -> Self {
-        Ray { origin, direction }
+    pub fn new(origin: Vec3, direction: Vec3, time: f32) -> Self {
+        Ray { origin, direction, time, wavelength: Wavelength::Rgb, absorption: Vec3::zeros(), scatter_depth: f32::INFINITY }
     }
 
     pub fn point_at_parameter(&self, t: f32) -> Vec3 {
         self.origin + (self.direction * t)
     }
+
+    // beer-lambert, the fraction of light left after travelling to `t` through
+    // the ray's medium, `t` may be infinite for rays that escape
+    pub fn transmittance(&self, t: f32) -> Vec3 {
+        if self.absorption == Vec3::zeros() {
+            return Vec3::ones();
+        }
+        let distance = t * self.direction.length();
+        let channel = |a: f32| if a > 0.0 { (-a * distance).exp() } else { 1.0 };
+        Vec3::new(channel(self.absorption.x), channel(self.absorption.y), channel(self.absorption.z))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transmittance_follows_beer_lambert() {
+        let mut ray = Ray::new(Vec3::zeros(), Vec3::new(0.0, 0.0, -2.0), 0.0);
+        assert_eq!(ray.transmittance(f32::INFINITY), Vec3::ones());
+        ray.absorption = Vec3::new(1.0, 0.5, 0.0);
+        // t is in units of the direction's length
+        let t = ray.transmittance(1.0);
+        assert!((t.x - (-2.0f32).exp()).abs() < 1e-6 && (t.y - (-1.0f32).exp()).abs() < 1e-6);
+        assert_eq!(t.z, 1.0);
+        assert_eq!(ray.transmittance(f32::INFINITY), Vec3::new(0.0, 0.0, 1.0));
+    }
 }
@@ -1,58 +1,595 @@
+use crate::hitable::{HitRecord, Hitable};
+use crate::light::{self, Light};
+use crate::background::Background;
+use crate::scene::Scene;
 use crate::camera::Camera;
-use crate::hitable::World;
+use crate::color::{luminance, ColorSettings};
+use crate::tile::{self, Tile, TileOrder};
+use crate::filter::Filter;
+use crate::spectrum::{self, Wavelength};
+use crate::medium;
 use crate::ray::Ray;
+use crate::sampler::{Sampler, SamplerKind};
 use crate::vec3::Vec3;
-use rand::prelude::*;
-use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, TryRecvError};
+use rayon::Yield;
 
-const NUM_SAMPLES: u32 = 128;
-const MAX_DEPTH: u32 = 16;
+#[derive(Copy, Clone)]
+pub struct Settings {
+    pub width: usize,
+    pub height: usize,
+    pub num_samples: u32,
+    pub max_depth: u32,
+    // the same seed renders the same image
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    // stop sampling pixels whose relative error drops below this
+    pub adaptive_threshold: Option<f32>,
+    // edge length of the square tiles in pixels
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    pub filter: Filter,
+    // trace a single wavelength per path, for materials that disperse light
+    pub spectral: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            width: 800,
+            height: 400,
+            num_samples: 128,
+            max_depth: 16,
+            seed: 0,
+            sampler: SamplerKind::Independent,
+            adaptive_threshold: None,
+            tile_size: 32,
+            tile_order: TileOrder::Spiral,
+            filter: Filter::default(),
+            spectral: false,
+        }
+    }
+}
+
+const T_MIN: f32 = 0.0001;
+
+fn color(ray: Ray, scene: &Scene, depth: u32, sampler: &mut dyn Sampler) -> Vec3 {
+    trace(ray, scene, depth, None, sampler)
+}
 
-fn color(ray: Ray, world: &World, depth: u32, rng: &mut ThreadRng) -> Vec3 {
-    if let Some(hit) = world.hit(ray, 0.0001, std::f32::MAX) {
-        if depth < MAX_DEPTH {
-            if let Some(scatter) = hit.material.scatter(ray, hit, rng) {
-                color(scatter.ray, world, depth + 1, rng) * scatter.attenuation
+// `bsdf_pdf` is the density the previous bounce sampled `ray` with, None for camera rays
+// and specular bounces which light sampling could not have found
+fn trace(ray: Ray, scene: &Scene, depth: u32, bsdf_pdf: Option<f32>, sampler: &mut dyn Sampler) -> Vec3 {
+    let ray = through_media(ray, scene, sampler);
+    let hit = match (scene.world.hit(ray, T_MIN, f32::MAX), &scene.fog) {
+        (Some(surface), Some(fog)) => fog.scatter_at(ray, surface.t, sampler.next_1d()).unwrap_or(surface),
+        (Some(surface), None) => surface,
+        (None, _) => return background(ray, scene, bsdf_pdf) * ray.transmittance(f32::INFINITY),
+    };
+    // whatever is found at the hit reaches the ray's origin through its medium
+    let transmittance = ray.transmittance(hit.t);
+    let mut emitted = hit.material.emitted(hit);
+    if let Some(bsdf_pdf) = bsdf_pdf {
+        if emitted != Vec3::zeros() {
+            let light_pdf = light::pdf_value(&scene.lights, ray.origin, ray.direction);
+            emitted = emitted * power_heuristic(bsdf_pdf, light_pdf);
+        }
+    }
+    if depth >= scene.settings.max_depth {
+        return emitted * transmittance;
+    }
+    match hit.material.scatter(ray, hit, sampler) {
+        Some(scatter) => {
+            let direct = if scatter.pdf.is_some() {
+                sample_light(ray, hit, scene, sampler)
             } else {
                 Vec3::zeros()
+            };
+            let incoming = trace(scatter.ray, scene, depth + 1, scatter.pdf, sampler);
+            let incoming = match scatter.wavelength {
+                Some(wavelength) => spectrum::project(wavelength, incoming),
+                None => incoming,
+            };
+            (emitted + direct + incoming * scatter.attenuation) * transmittance
+        }
+        None => emitted * transmittance,
+    }
+}
+
+// draws how far into the media of the scene the ray gets before it scatters,
+// scenes without media leave the sample to the rest of the path
+fn through_media(ray: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Ray {
+    if scene.media {
+        Ray { scatter_depth: medium::scatter_depth(sampler.next_1d()), ..ray }
+    } else {
+        ray
+    }
+}
+
+// light from an environment map is weighted against sampling it as a light
+fn background(ray: Ray, scene: &Scene, bsdf_pdf: Option<f32>) -> Vec3 {
+    let radiance = scene.background.color(ray);
+    match (bsdf_pdf, &scene.background) {
+        (Some(bsdf_pdf), Background::Environment(_)) => {
+            radiance * power_heuristic(bsdf_pdf, light::pdf_value(&scene.lights, ray.origin, ray.direction))
+        }
+        _ => radiance,
+    }
+}
+
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
+
+// next event estimation, light reflected at `hit` from one randomly chosen light
+fn sample_light(ray: Ray, hit: HitRecord, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3 {
+    let lights = &scene.lights;
+    if lights.is_empty() {
+        return Vec3::zeros();
+    }
+    let num_lights = lights.len() as f32;
+    let index = ((sampler.next_1d() * num_lights) as usize).min(lights.len() - 1);
+    match &lights[index] {
+        Light::Area(object) => {
+            let direction = object.random(hit.p, sampler);
+            let f = hit.material.eval(ray, hit, direction);
+            if f == Vec3::zeros() {
+                return Vec3::zeros();
             }
+            let shadow_ray = through_media(hit.spawn_ray(direction), scene, sampler);
+            // whatever the ray hits first is what the light sample sees
+            let radiance = match scene.world.hit(shadow_ray, T_MIN, f32::MAX) {
+                Some(light_hit) => {
+                    light_hit.material.emitted(light_hit)
+                        * shadow_ray.transmittance(light_hit.t)
+                        * fog_transmittance(scene, light_hit.t * direction.length())
+                }
+                None => return Vec3::zeros(),
+            };
+            let light_pdf = light::pdf_value(lights, shadow_ray.origin, direction);
+            if light_pdf <= 0.0 {
+                return Vec3::zeros();
+            }
+            let bsdf_pdf = hit.material.pdf(ray, hit, direction);
+            f * radiance * (power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
         }
-        else {
-            Vec3::zeros()
+        Light::Point { position, intensity } => {
+            let to_light = *position - hit.p;
+            let shadow_ray = through_media(hit.spawn_ray(to_light), scene, sampler);
+            // the shadow ray reaches the light at t = 1
+            if scene.world.hit(shadow_ray, T_MIN, 1.0 - T_MIN).is_some() {
+                return Vec3::zeros();
+            }
+            let transmittance = fog_transmittance(scene, to_light.length());
+            hit.material.eval(ray, hit, to_light) * *intensity * shadow_ray.transmittance(1.0)
+                * (transmittance * num_lights / to_light.squared_length())
+        }
+        Light::Directional { direction, irradiance } => {
+            let to_light = -*direction;
+            let shadow_ray = through_media(hit.spawn_ray(to_light), scene, sampler);
+            if scene.world.hit(shadow_ray, T_MIN, f32::MAX).is_some() {
+                return Vec3::zeros();
+            }
+            hit.material.eval(ray, hit, to_light) * *irradiance * shadow_ray.transmittance(f32::INFINITY) * num_lights
+        }
+        Light::Environment(map) => {
+            let (direction, map_pdf) = map.sample(sampler.next_2d());
+            if map_pdf <= 0.0 {
+                return Vec3::zeros();
+            }
+            let f = hit.material.eval(ray, hit, direction);
+            if f == Vec3::zeros() {
+                return Vec3::zeros();
+            }
+            let shadow_ray = through_media(hit.spawn_ray(direction), scene, sampler);
+            if scene.world.hit(shadow_ray, T_MIN, f32::MAX).is_some() {
+                return Vec3::zeros();
+            }
+            let light_pdf = light::pdf_value(lights, shadow_ray.origin, direction);
+            let bsdf_pdf = hit.material.pdf(ray, hit, direction);
+            f * map.radiance(direction) * shadow_ray.transmittance(f32::INFINITY) * (power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
         }
-    } else {
-        let unit_direction = ray.direction.make_unit_vector();
-        let t = 0.5 * (unit_direction.y + 1.0);
-        Vec3::ones() * (1.0 - t)  + Vec3::new(0.5, 0.7, 1.0) * t
     }
 }
 
+// directional lights are as far as the background and, like it, not dimmed by fog
+fn fog_transmittance(scene: &Scene, distance: f32) -> f32 {
+    scene.fog.as_ref().map_or(1.0, |fog| fog.transmittance(distance))
+}
+
 fn to_bgra(r: u32, g: u32, b: u32) -> u32 {
     255 << 24 | r << 16 | g << 8 | b
 }
 
-pub fn render(width: usize, height: usize, camera: Camera, world: World) -> Vec<u32> {
-    (0..width * height)
-        .into_par_iter()
-        .map_init(
-            || thread_rng(),
-            |mut rng, screen_pos| {
-                let mut col = Vec3::zeros();
-                let i = height - 1 - screen_pos / width;
-                let j = screen_pos % width;
-                for _ in 0..NUM_SAMPLES {
-                    let u = ((j as f32) + rng.gen::<f32>()) / (width as f32);
-                    let v = ((i as f32) + rng.gen::<f32>()) / (height as f32);
-                    let ray = camera.get_ray(u, v, &mut rng);
-                    col = col + color(ray, &world, 0, &mut rng);
+pub fn to_bgra_pixel(color: &ColorSettings, col: Vec3, pixel: usize) -> u32 {
+    let [r, g, b] = color.to_rgb8(col, pixel);
+    to_bgra(r as u32, g as u32, b as u32)
+}
+
+pub fn to_bgra_buffer(color: &ColorSettings, pixels: &[Vec3]) -> Vec<u32> {
+    pixels.iter().enumerate().map(|(p, col)| to_bgra_pixel(color, *col, p)).collect()
+}
+
+// pixels sample at least this often before they can count as converged
+const MIN_ADAPTIVE_SAMPLES: u32 = 16;
+// noisy pixels may take up to this many times the samples per pixel
+const MAX_ADAPTIVE_FACTOR: u32 = 4;
+// keeps the relative error of near black pixels finite
+const MIN_LUMINANCE: f32 = 0.01;
+
+// running statistics of the samples taken for one pixel
+#[derive(Copy, Clone)]
+pub struct PixelStats {
+    pub sum: Vec3,
+    // sum of the squared luminance of the samples
+    pub sum_sq: f32,
+    pub samples: u32,
+}
+
+impl PixelStats {
+    pub fn add(&mut self, col: Vec3) {
+        self.sum = self.sum + col;
+        self.sum_sq += luminance(col) * luminance(col);
+        self.samples += 1;
+    }
+
+    pub fn mean(&self) -> Vec3 {
+        if self.samples > 0 { self.sum / self.samples as f32 } else { Vec3::zeros() }
+    }
+
+    // standard error of the mean luminance relative to the luminance itself
+    pub fn relative_error(&self) -> f32 {
+        if self.samples < 2 {
+            return f32::INFINITY;
+        }
+        let n = self.samples as f32;
+        let mean = luminance(self.sum) / n;
+        let variance = ((self.sum_sq - mean * mean * n) / (n - 1.0)).max(0.0);
+        (variance / n).sqrt() / mean.max(MIN_LUMINANCE)
+    }
+}
+
+// the samples splatted onto a pixel by the reconstruction filter
+#[derive(Copy, Clone)]
+pub struct Splat {
+    pub sum: Vec3,
+    pub weight: f32,
+}
+
+impl Splat {
+    pub fn zero() -> Splat {
+        Splat { sum: Vec3::zeros(), weight: 0.0 }
+    }
+}
+
+// per pixel statistics of the samples taken so far, top row first
+pub struct Accumulator {
+    pub width: usize,
+    pub height: usize,
+    // what adaptive sampling and the heat map go by, for the samples taken in each pixel
+    pub pixels: Vec<PixelStats>,
+    // what the image is made of, for the samples landing near each pixel
+    pub splats: Vec<Splat>,
+    pub passes: u32,
+    pub total_samples: u64,
+    // pixels sampled by the last pass
+    pub active: usize,
+}
+
+impl Accumulator {
+    pub fn new(width: usize, height: usize) -> Accumulator {
+        let empty = PixelStats { sum: Vec3::zeros(), sum_sq: 0.0, samples: 0 };
+        Accumulator {
+            width,
+            height,
+            pixels: vec![empty; width * height],
+            splats: vec![Splat::zero(); width * height],
+            passes: 0,
+            total_samples: 0,
+            active: width * height,
+        }
+    }
+
+    // the linear (unclamped) radiance of each pixel, the filter weighted average
+    // of the samples around it
+    pub fn image(&self) -> Vec<Vec3> {
+        self.splats
+            .iter()
+            .zip(&self.pixels)
+            .map(|(splat, pixel)| {
+                // negative lobes can cancel out the weights of a barely sampled pixel
+                if splat.weight > 1e-4 { splat.sum / splat.weight } else { pixel.mean() }
+            })
+            .collect()
+    }
+
+    // samples taken per pixel, from blue for the fewest to red for the most
+    pub fn heat_map(&self) -> Vec<Vec3> {
+        let max = self.pixels.iter().map(|p| p.samples).max().unwrap_or(0).max(1) as f32;
+        self.pixels
+            .iter()
+            .map(|p| {
+                let t = p.samples as f32 / max;
+                Vec3::new(t, 1.0 - (2.0 * t - 1.0).abs(), 1.0 - t)
+            })
+            .collect()
+    }
+
+    fn budget(&self, settings: &Settings) -> u64 {
+        (self.width * self.height) as u64 * settings.num_samples as u64
+    }
+
+    // whether the sample budget has been spent, or no pixel needed samples in
+    // the last pass
+    pub fn done(&self, settings: &Settings) -> bool {
+        self.active == 0 || self.total_samples >= self.budget(settings)
+    }
+
+    // fraction of the sample budget spent
+    pub fn progress(&self, settings: &Settings) -> f32 {
+        if self.done(settings) {
+            return 1.0;
+        }
+        (self.total_samples as f64 / self.budget(settings).max(1) as f64).min(1.0) as f32
+    }
+}
+
+fn needs_samples(pixel: &PixelStats, settings: &Settings) -> bool {
+    match settings.adaptive_threshold {
+        Some(threshold) => pixel.samples < settings.num_samples * MAX_ADAPTIVE_FACTOR
+            && (pixel.samples < MIN_ADAPTIVE_SAMPLES.min(settings.num_samples)
+                || pixel.relative_error() > threshold),
+        None => pixel.samples < settings.num_samples,
+    }
+}
+
+// the tile grown by the pixels its samples can splat onto
+fn splat_region(tile: &Tile, settings: &Settings) -> Tile {
+    let margin = settings.filter.margin();
+    let (x, y) = (tile.x.saturating_sub(margin), tile.y.saturating_sub(margin));
+    Tile {
+        x,
+        y,
+        width: (tile.x + tile.width + margin).min(settings.width) - x,
+        height: (tile.y + tile.height + margin).min(settings.height) - y,
+    }
+}
+
+// `splats` covers the `splat_region` of the tile
+fn render_tile(scene: &Scene, camera: &Camera, tile: Tile, pixels: &mut [PixelStats], splats: &mut [Splat]) -> usize {
+    let Settings { width, height, seed, num_samples, sampler, filter, spectral, .. } = scene.settings;
+    let region = splat_region(&tile, &scene.settings);
+    let mut active = 0;
+    for (screen_pos, pixel) in tile.pixels(width).zip(pixels.iter_mut()) {
+        if !needs_samples(pixel, &scene.settings) {
+            continue;
+        }
+        let sampler = &mut *sampler.sampler(seed, screen_pos, pixel.samples, num_samples);
+        let i = height - 1 - screen_pos / width;
+        let j = screen_pos % width;
+        let (du, dv) = sampler.next_2d();
+        let u = ((j as f32) + du) / (width as f32);
+        let v = ((i as f32) + dv) / (height as f32);
+        let mut ray = camera.get_ray(u, v, sampler);
+        if spectral {
+            ray.wavelength = Wavelength::Pending;
+        }
+        let col = color(ray, scene, 0, sampler);
+        pixel.add(col);
+        active += 1;
+
+        // position of the sample on the film, rows counting from the top
+        let (fx, fy) = (j as f32 + du, (height - i) as f32 - dv);
+        let x0 = ((fx - filter.radius - 0.5).ceil().max(0.0) as usize).max(region.x);
+        let y0 = ((fy - filter.radius - 0.5).ceil().max(0.0) as usize).max(region.y);
+        let x1 = ((fx + filter.radius - 0.5).floor().max(0.0) as usize).min(region.x + region.width - 1);
+        let y1 = ((fy + filter.radius - 0.5).floor().max(0.0) as usize).min(region.y + region.height - 1);
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let weight = filter.eval(fx - (x as f32 + 0.5), fy - (y as f32 + 0.5));
+                if weight != 0.0 {
+                    let splat = &mut splats[(y - region.y) * region.width + (x - region.x)];
+                    splat.sum = splat.sum + col * weight;
+                    splat.weight += weight;
+                }
+            }
+        }
+    }
+    active
+}
+
+// adds one sample to every pixel short of the samples per pixel, or with adaptive
+// sampling to every pixel that has not converged yet, a tile at a time
+//
+// `on_tile` runs on the calling thread after the samples of each tile are added to
+// `accum.pixels`, once it returns false the rest of the pass is skipped, the samples
+// already taken are dropped and so is the return value
+//
+// tiles splat onto their neighbours' pixels as well, the splats are added to
+// `accum.splats` at the end of the pass, top to bottom, so that the sums don't
+// depend on the tile order or which thread finished first, `accum` is only whole,
+// and ready to checkpoint, once this returns
+pub fn render_pass<F>(scene: &Scene, camera: &Camera, accum: &mut Accumulator, mut on_tile: F) -> bool
+    where F: FnMut(&Tile, &Accumulator) -> bool
+{
+    let Settings { width, height, tile_size, tile_order, .. } = scene.settings;
+    // each tile works on its own copy of its pixels
+    let work: Vec<(Tile, Vec<PixelStats>)> = tile::tiles(width, height, tile_size, tile_order)
+        .into_iter()
+        .map(|tile| (tile, tile.pixels(width).map(|p| accum.pixels[p]).collect()))
+        .collect();
+    let mut tile_splats = Vec::with_capacity(work.len());
+    let cancelled = AtomicBool::new(false);
+    // tiles are handed out in the tile order, so they finish roughly in that order
+    let next = AtomicUsize::new(0);
+    let mut active = 0;
+    let (sender, receiver) = mpsc::channel();
+    rayon::in_place_scope(|s| {
+        let (work, next, cancelled) = (&work, &next, &cancelled);
+        // one worker per thread of the pool, each takes the next tile until none are left
+        for _ in 0..rayon::current_num_threads().min(work.len()) {
+            let sender = sender.clone();
+            s.spawn(move |_| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= work.len() || cancelled.load(Ordering::Relaxed) {
+                    break;
                 }
-                col = col * (1.0 / NUM_SAMPLES as f32);
-                let ir = (255.99 * col.x.sqrt()) as u32;
-                let ig = (255.99 * col.y.sqrt()) as u32;
-                let ib = (255.99 * col.z.sqrt()) as u32;
-
-                to_bgra(ir, ig, ib)
-            },
-        )
-        .collect()
+                let (tile, ref pixels) = work[i];
+                let mut pixels = pixels.clone();
+                let region = splat_region(&tile, &scene.settings);
+                let mut splats = vec![Splat::zero(); region.width * region.height];
+                let tile_active = render_tile(scene, camera, tile, &mut pixels, &mut splats);
+                sender.send((tile, pixels, splats, tile_active)).unwrap();
+            });
+        }
+        drop(sender);
+        loop {
+            let (tile, pixels, splats, tile_active) = match receiver.try_recv() {
+                Ok(finished) => finished,
+                Err(TryRecvError::Disconnected) => break,
+                // a calling thread that is part of the pool runs the workers it is
+                // waiting for when no other thread took them
+                Err(TryRecvError::Empty) => match rayon::yield_now() {
+                    Some(Yield::Executed) => continue,
+                    _ => match receiver.recv() {
+                        Ok(finished) => finished,
+                        Err(_) => break,
+                    },
+                },
+            };
+            for (p, pixel) in tile.pixels(width).zip(pixels) {
+                accum.pixels[p] = pixel;
+            }
+            tile_splats.push((splat_region(&tile, &scene.settings), splats));
+            active += tile_active;
+            accum.total_samples += tile_active as u64;
+            if !cancelled.load(Ordering::Relaxed) && !on_tile(&tile, accum) {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+    // a cancelled pass is taken back, so `accum` always holds whole passes and a
+    // render resumed from it takes the same samples as one that never stopped
+    if cancelled.into_inner() {
+        for (tile, pixels) in work {
+            for (p, pixel) in tile.pixels(width).zip(pixels) {
+                accum.pixels[p] = pixel;
+            }
+        }
+        accum.total_samples -= active as u64;
+        return false;
+    }
+    tile_splats.sort_by_key(|(region, _)| (region.y, region.x));
+    for (region, splats) in tile_splats {
+        for (p, splat) in region.pixels(width).zip(splats) {
+            let total = &mut accum.splats[p];
+            total.sum = total.sum + splat.sum;
+            total.weight += splat.weight;
+        }
+    }
+    accum.passes += 1;
+    accum.active = active;
+    true
+}
+
+// keeps adding samples to `accum` until done, see `Accumulator::image` for the radiance
+pub fn render<F>(scene: &Scene, mut accum: Accumulator, mut on_tile: F) -> Accumulator
+    where F: FnMut(&Tile, &Accumulator)
+{
+    let Settings { width, height, .. } = scene.settings;
+    let camera = scene.camera.camera(width as f32 / height as f32);
+    while !accum.done(&scene.settings) {
+        render_pass(scene, &camera, &mut accum, |tile, accum| {
+            on_tile(tile, accum);
+            true
+        });
+    }
+    accum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkpoint;
+    use std::path::Path;
+
+    // a filter wider than a pixel makes tiles splat onto each other, and
+    // adaptive sampling makes later passes depend on earlier ones
+    const SCENE: &str = r#"
+        [render]
+        width = 24
+        height = 16
+        samples = 4
+        tile_size = 5
+        filter = "mitchell"
+        adaptive_threshold = 0.1
+
+        [camera]
+        look_from = [0.0, 1.0, 4.0]
+        look_to = [0.0, 0.5, 0.0]
+        vfov = 40.0
+
+        [[plane]]
+        point = [0.0, 0.0, 0.0]
+        normal = [0.0, 1.0, 0.0]
+        material = { type = "lambertian", albedo = [0.5, 0.5, 0.5] }
+
+        [[sphere]]
+        center = [0.0, 0.5, 0.0]
+        radius = 0.5
+        material = { type = "dielectric", ref_idx = 1.5 }
+    "#;
+
+    fn render_with_threads(threads: usize) -> Vec<Vec3> {
+        let scene = Scene::from_toml(SCENE, Path::new("")).unwrap();
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        let accum = pool.install(|| render(&scene, Accumulator::new(24, 16), |_, _| {}));
+        accum.image()
+    }
+
+    #[test]
+    fn renders_are_independent_of_the_thread_count() {
+        let single = render_with_threads(1);
+        assert!(single.iter().any(|c| c.x > 0.0));
+        for &threads in &[2, 4] {
+            let multi = render_with_threads(threads);
+            let same_bits = single.iter().zip(&multi).all(|(a, b)| {
+                (a.x.to_bits(), a.y.to_bits(), a.z.to_bits()) == (b.x.to_bits(), b.y.to_bits(), b.z.to_bits())
+            });
+            assert!(same_bits, "{} threads rendered a different image", threads);
+        }
+    }
+
+    #[test]
+    fn renders_stopped_mid_pass_resume_as_if_never_stopped() {
+        let scene = Scene::from_toml(SCENE, Path::new("")).unwrap();
+        let camera = scene.camera.camera(24.0 / 16.0);
+        let uninterrupted = render(&scene, Accumulator::new(24, 16), |_, _| {});
+
+        // cut the second pass short after a few tiles, as closing the window does, and
+        // checkpoint what is left
+        let mut accum = Accumulator::new(24, 16);
+        assert!(render_pass(&scene, &camera, &mut accum, |_, _| true));
+        let first_pass = accum.pixels.clone();
+        let mut tiles = 0;
+        assert!(!render_pass(&scene, &camera, &mut accum, |_, _| {
+            tiles += 1;
+            tiles < 3
+        }));
+        assert_eq!((accum.passes, accum.total_samples), (1, 24 * 16));
+        assert!(accum.pixels.iter().zip(&first_pass).all(|(a, b)| a.samples == b.samples && a.sum == b.sum));
+        let path = std::env::temp_dir().join(format!("raytrace-{}-mid-pass.ckpt", std::process::id()));
+        checkpoint::save(&path, &scene.settings, 7, &accum).unwrap();
+        let resumed = checkpoint::load(&path, &scene.settings, 7).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let resumed = render(&scene, resumed, |_, _| {});
+
+        assert_eq!(resumed.total_samples, uninterrupted.total_samples);
+        assert!(resumed.pixels.iter().zip(&uninterrupted.pixels).all(|(a, b)| a.samples == b.samples && a.sum == b.sum));
+        assert!(resumed.image().iter().zip(&uninterrupted.image()).all(|(a, b)| {
+            (a.x.to_bits(), a.y.to_bits(), a.z.to_bits()) == (b.x.to_bits(), b.y.to_bits(), b.z.to_bits())
+        }));
+    }
 }
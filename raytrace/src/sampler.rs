@@ -0,0 +1,380 @@
+use std::str::FromStr;
+
+use rand::prelude::*;
+use serde::{Deserialize};
+
+use crate::{
+    random::{self, SampleRng},
+};
+
+// the top 24 bits of a hash as a float in [0, 1), as many as an f32 holds exactly
+pub fn unit_float(bits: u64) -> f32 {
+    (bits >> 40) as f32 / (1u32 << 24) as f32
+}
+
+// supplies the random numbers for one sample of one pixel, each call moves on to
+// the next dimension of the sample
+pub trait Sampler {
+    // in [0, 1)
+    fn next_1d(&mut self) -> f32;
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    // the sampler for sample `sample` out of `num_samples` of `pixel`
+    pub fn sampler(self, seed: u64, pixel: usize, sample: u32, num_samples: u32) -> Box<dyn Sampler> {
+        let rng = random::sample_rng(seed, pixel, sample);
+        // the same for every sample of the pixel
+        let key = random::hash(seed, pixel as u64, u64::MAX);
+        match self {
+            SamplerKind::Independent => Box::new(Independent { rng }),
+            SamplerKind::Stratified => Box::new(Stratified { rng, key, sample, num_samples, dimension: 0 }),
+            SamplerKind::Halton => Box::new(Halton { rng, key, sample, dimension: 0 }),
+            SamplerKind::Sobol => Box::new(Sobol { rng, key, sample, dimension: 0 }),
+        }
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SamplerKind, String> {
+        match s {
+            "independent" => Ok(SamplerKind::Independent),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            _ => Err(format!("unknown sampler {}, expected independent, stratified, halton or sobol", s)),
+        }
+    }
+}
+
+// largest f32 below one
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+// uniform random numbers
+pub struct Independent {
+    rng: SampleRng,
+}
+
+impl Sampler for Independent {
+    fn next_1d(&mut self) -> f32 {
+        self.rng.gen::<f32>()
+    }
+}
+
+// jittered strata, each pixel visits the strata of a dimension in its own random order
+pub struct Stratified {
+    rng: SampleRng,
+    key: u64,
+    sample: u32,
+    num_samples: u32,
+    dimension: u32,
+}
+
+impl Stratified {
+    // the stratum out of `count` this sample falls into
+    fn stratum(&mut self, count: u32) -> u32 {
+        let round = self.sample / count;
+        let key = random::hash(self.key, self.dimension as u64, round as u64) as u32;
+        self.dimension += 1;
+        permute(self.sample % count, count, key)
+    }
+}
+
+impl Sampler for Stratified {
+    fn next_1d(&mut self) -> f32 {
+        let count = self.num_samples.max(1);
+        let stratum = self.stratum(count);
+        ((stratum as f32 + self.rng.gen::<f32>()) / count as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    // a square grid with at least as many cells as samples
+    fn next_2d(&mut self) -> (f32, f32) {
+        let n = (self.num_samples.max(1) as f32).sqrt().ceil() as u32;
+        let cell = self.stratum(n * n);
+        let x = ((cell % n) as f32 + self.rng.gen::<f32>()) / n as f32;
+        let y = ((cell / n) as f32 + self.rng.gen::<f32>()) / n as f32;
+        (x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON))
+    }
+}
+
+// kensler's hashed permutation of [0, n), a different one for each key
+fn permute(mut i: u32, n: u32, key: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= key;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= key >> 16;
+        i ^= (i & w) >> 4;
+        i ^= key >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= key >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | key >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    (i.wrapping_add(key)) % n
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+// the radical inverse in a different prime base for each dimension, randomly
+// shifted per pixel, dimensions past the table fall back to uniform numbers
+pub struct Halton {
+    rng: SampleRng,
+    key: u64,
+    sample: u32,
+    dimension: u32,
+}
+
+impl Sampler for Halton {
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        let base = match PRIMES.get(dimension as usize) {
+            Some(&base) => base,
+            None => return self.rng.gen::<f32>(),
+        };
+        let shift = unit_float(random::hash(self.key, dimension as u64, 0));
+        (radical_inverse(base, self.sample) + shift).fract().min(ONE_MINUS_EPSILON)
+    }
+}
+
+fn radical_inverse(base: u32, mut index: u32) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv = inv_base;
+    let mut result = 0.0f64;
+    while index > 0 {
+        result += (index % base) as f64 * inv;
+        index /= base;
+        inv *= inv_base;
+    }
+    result as f32
+}
+
+// (s, a, m) of joe and kuo's direction numbers for the dimensions after the first
+const SOBOL_PARAMETERS: [(u32, u32, [u32; 7]); 20] = [
+    (1, 0, [1, 0, 0, 0, 0, 0, 0]),
+    (2, 1, [1, 3, 0, 0, 0, 0, 0]),
+    (3, 1, [1, 3, 1, 0, 0, 0, 0]),
+    (3, 2, [1, 1, 1, 0, 0, 0, 0]),
+    (4, 1, [1, 1, 3, 3, 0, 0, 0]),
+    (4, 4, [1, 3, 5, 13, 0, 0, 0]),
+    (5, 2, [1, 1, 5, 5, 17, 0, 0]),
+    (5, 4, [1, 1, 5, 5, 5, 0, 0]),
+    (5, 7, [1, 1, 7, 11, 19, 0, 0]),
+    (5, 11, [1, 1, 5, 1, 1, 0, 0]),
+    (5, 13, [1, 1, 1, 3, 11, 0, 0]),
+    (5, 14, [1, 3, 5, 5, 31, 0, 0]),
+    (6, 1, [1, 3, 3, 9, 7, 49, 0]),
+    (6, 13, [1, 1, 1, 15, 21, 21, 0]),
+    (6, 16, [1, 3, 1, 13, 27, 49, 0]),
+    (6, 19, [1, 1, 1, 15, 7, 5, 0]),
+    (6, 22, [1, 3, 1, 15, 13, 25, 0]),
+    (6, 25, [1, 1, 5, 5, 19, 61, 0]),
+    (7, 1, [1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, [1, 3, 7, 13, 13, 15, 69]),
+];
+
+const SOBOL_DIMENSIONS: usize = SOBOL_PARAMETERS.len() + 1;
+
+// a column of the generator matrix for each bit of the index
+const SOBOL_MATRICES: [[u32; 32]; SOBOL_DIMENSIONS] = sobol_matrices();
+
+const fn sobol_matrices() -> [[u32; 32]; SOBOL_DIMENSIONS] {
+    let mut matrices = [[0u32; 32]; SOBOL_DIMENSIONS];
+    // the first dimension is the van der corput sequence
+    let mut i = 0;
+    while i < 32 {
+        matrices[0][i] = 1 << (31 - i);
+        i += 1;
+    }
+    let mut d = 1;
+    while d < SOBOL_DIMENSIONS {
+        let (s, a, m) = SOBOL_PARAMETERS[d - 1];
+        let s = s as usize;
+        let mut i = 0;
+        while i < 32 {
+            matrices[d][i] = if i < s {
+                m[i] << (31 - i)
+            } else {
+                let mut v = matrices[d][i - s] ^ (matrices[d][i - s] >> s);
+                let mut k = 1;
+                while k < s {
+                    if (a >> (s - 1 - k)) & 1 == 1 {
+                        v ^= matrices[d][i - k];
+                    }
+                    k += 1;
+                }
+                v
+            };
+            i += 1;
+        }
+        d += 1;
+    }
+    matrices
+}
+
+// the sobol sequence with a random digital shift per pixel, dimensions past the
+// table fall back to uniform numbers
+pub struct Sobol {
+    rng: SampleRng,
+    key: u64,
+    sample: u32,
+    dimension: u32,
+}
+
+impl Sampler for Sobol {
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.dimension as usize;
+        self.dimension += 1;
+        let matrix = match SOBOL_MATRICES.get(dimension) {
+            Some(matrix) => matrix,
+            None => return self.rng.gen::<f32>(),
+        };
+        let mut bits = random::hash(self.key, dimension as u64, 0) as u32;
+        let mut index = self.sample;
+        let mut column = 0;
+        while index > 0 {
+            if index & 1 == 1 {
+                bits ^= matrix[column];
+            }
+            index >>= 1;
+            column += 1;
+        }
+        (bits as f32 / 4_294_967_296.0).min(ONE_MINUS_EPSILON)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the first `dimensions` numbers of each of the `num_samples` samples of a pixel
+    fn samples(kind: SamplerKind, num_samples: u32, dimensions: usize) -> Vec<Vec<f32>> {
+        (0..num_samples)
+            .map(|sample| {
+                let mut sampler = kind.sampler(7, 42, sample, num_samples);
+                (0..dimensions).map(|_| sampler.next_1d()).collect()
+            })
+            .collect()
+    }
+
+    // the cell each value of a dimension falls in, out of `n` equal cells
+    fn cells(values: &[f32], n: u32) -> Vec<u32> {
+        let mut cells: Vec<u32> = values.iter().map(|v| (v * n as f32) as u32).collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    #[test]
+    fn samples_are_in_the_unit_interval_and_repeatable() {
+        for &kind in &[SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+            // past the end of the halton and sobol tables as well
+            let first = samples(kind, 64, 40);
+            assert!(first.iter().flatten().all(|&v| (0.0..1.0).contains(&v)), "{:?}", kind);
+            assert!(first == samples(kind, 64, 40), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn radical_inverse_mirrors_the_digits() {
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 2), 0.25);
+        assert_eq!(radical_inverse(2, 3), 0.75);
+        assert!((radical_inverse(3, 1) - 1.0 / 3.0).abs() < 1e-7);
+        assert!((radical_inverse(3, 3) - 1.0 / 9.0).abs() < 1e-7);
+        assert!((radical_inverse(3, 5) - 7.0 / 9.0).abs() < 1e-7);
+    }
+
+    #[test]
+    fn permute_is_a_permutation() {
+        for &n in &[1, 2, 5, 16, 100] {
+            for key in 0..8u32 {
+                let mut p: Vec<u32> = (0..n).map(|i| permute(i, n, key.wrapping_mul(0x9e37_79b9))).collect();
+                p.sort_unstable();
+                assert_eq!(p, (0..n).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_samples_fill_every_stratum() {
+        let n = 16;
+        let points = samples(SamplerKind::Stratified, n, 3);
+        for d in 0..3 {
+            let values: Vec<f32> = points.iter().map(|p| p[d]).collect();
+            assert_eq!(cells(&values, n), (0..n).collect::<Vec<_>>(), "dimension {}", d);
+        }
+        // a 4 by 4 grid for 2d samples
+        let mut grid: Vec<u32> = (0..n)
+            .map(|sample| {
+                let (x, y) = SamplerKind::Stratified.sampler(7, 42, sample, n).next_2d();
+                (y * 4.0) as u32 * 4 + (x * 4.0) as u32
+            })
+            .collect();
+        grid.sort_unstable();
+        assert_eq!(grid, (0..n).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sobol_points_are_stratified() {
+        let points = samples(SamplerKind::Sobol, 64, SOBOL_DIMENSIONS);
+        // each dimension on its own, one point per 1/64th
+        for d in 0..SOBOL_DIMENSIONS {
+            let values: Vec<f32> = points.iter().map(|p| p[d]).collect();
+            assert_eq!(cells(&values, 64), (0..64).collect::<Vec<_>>(), "dimension {}", d);
+        }
+        // the first two dimensions together, one point per cell of an 8 by 8 grid
+        let mut grid: Vec<u32> = points.iter().map(|p| (p[1] * 8.0) as u32 * 8 + (p[0] * 8.0) as u32).collect();
+        grid.sort_unstable();
+        assert_eq!(grid, (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn halton_points_are_evenly_spaced() {
+        // the first base^k points are spaced 1/base^k apart, whatever the shift
+        let points = samples(SamplerKind::Halton, 243, 2);
+        for &(d, n) in &[(0, 128u32), (1, 243)] {
+            let mut offsets: Vec<u32> = points
+                .iter()
+                .take(n as usize)
+                .map(|p| ((p[d] - points[0][d]).rem_euclid(1.0) * n as f32).round() as u32 % n)
+                .collect();
+            offsets.sort_unstable();
+            assert_eq!(offsets, (0..n).collect::<Vec<_>>(), "dimension {}", d);
+        }
+    }
+}
@@ -0,0 +1,1036 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rand::prelude::*;
+use serde::{Deserialize, Deserializer, de};
+
+use crate::{
+    vec3::{Vec3},
+    hitable::{Hitable, Sphere, World},
+    shapes::{Plane, Rect, Cuboid, Triangle, Disk, Cylinder},
+    mesh::{Mesh},
+    transform::{Instance, Transform},
+    medium::{ConstantMedium, Fog},
+    camera::{CameraSettings},
+    material::{Material, Lambertian, Metal, Dielectric, Ior, DiffuseLight, Microfacet, Fresnel, Volume},
+    texture::{Texture, Image, Noise, NoiseKind, Perlin},
+    light::{Light},
+    background::{Background},
+    environment::{EnvironmentMap},
+    color::{ColorSettings, ToneMap, Transfer},
+    renderer::{Settings},
+    sampler::{SamplerKind},
+    tile::{TileOrder},
+    filter::{Filter, FilterKind},
+    spectrum::{WAVELENGTH_MIN, WAVELENGTH_D, WAVELENGTH_MAX},
+};
+
+pub struct Scene {
+    pub settings: Settings,
+    pub camera: CameraSettings,
+    pub world: World,
+    pub background: Background,
+    pub lights: Vec<Light>,
+    pub color: ColorSettings,
+    pub fog: Option<Fog>,
+    // whether any shape is filled with a medium
+    pub media: bool,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    // reports the line and column of the offending value
+    Parse(toml::de::Error),
+    Mesh(PathBuf, io::Error),
+    Texture(PathBuf, image::ImageError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "could not read scene: {}", e),
+            SceneError::Parse(e) => write!(f, "invalid scene: {}", e),
+            SceneError::Mesh(path, e) => write!(f, "could not load mesh {}: {}", path.display(), e),
+            SceneError::Texture(path, e) => write!(f, "could not load texture {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(e: io::Error) -> SceneError {
+        SceneError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for SceneError {
+    fn from(e: toml::de::Error) -> SceneError {
+        SceneError::Parse(e)
+    }
+}
+
+impl Scene {
+    pub fn load(path: &Path) -> Result<Scene, SceneError> {
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Scene::from_toml(&fs::read_to_string(path)?, base_dir)
+    }
+
+    // files referenced by the scene are relative to `base_dir`
+    pub fn from_toml(text: &str, base_dir: &Path) -> Result<Scene, SceneError> {
+        let desc: SceneDesc = toml::from_str(text)?;
+        desc.build(base_dir)
+    }
+}
+
+fn positive<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where D: Deserializer<'de>, T: Deserialize<'de> + PartialOrd + Default + fmt::Display
+{
+    let value = T::deserialize(deserializer)?;
+    if value > T::default() {
+        Ok(value)
+    } else {
+        Err(de::Error::custom(format!("expected a positive value, found {}", value)))
+    }
+}
+
+fn non_negative<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where D: Deserializer<'de>, T: Deserialize<'de> + PartialOrd + Default + fmt::Display
+{
+    let value = T::deserialize(deserializer)?;
+    if value >= T::default() {
+        Ok(value)
+    } else {
+        Err(de::Error::custom(format!("expected a non-negative value, found {}", value)))
+    }
+}
+
+fn non_negative_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec3, D::Error> {
+    let c = Vec3::deserialize(deserializer)?;
+    if c.x >= 0.0 && c.y >= 0.0 && c.z >= 0.0 {
+        Ok(c)
+    } else {
+        Err(de::Error::custom(format!("expected non-negative values, found [{}, {}, {}]", c.x, c.y, c.z)))
+    }
+}
+
+fn unit_interval<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let value = f32::deserialize(deserializer)?;
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(de::Error::custom(format!("expected a value between 0 and 1, found {}", value)))
+    }
+}
+
+fn unit_interval_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    unit_interval(deserializer).map(Some)
+}
+
+fn positive_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where D: Deserializer<'de>, T: Deserialize<'de> + PartialOrd + Default + fmt::Display
+{
+    positive(deserializer).map(Some)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    render: RenderDesc,
+    camera: CameraDesc,
+    background: Option<BackgroundDesc>,
+    #[serde(default)]
+    color: ColorDesc,
+    fog: Option<FogDesc>,
+    #[serde(default, rename = "sphere")]
+    spheres: Vec<SphereDesc>,
+    #[serde(default, rename = "plane")]
+    planes: Vec<PlaneDesc>,
+    #[serde(default, rename = "rect")]
+    rects: Vec<RectDesc>,
+    #[serde(default, rename = "box")]
+    boxes: Vec<BoxDesc>,
+    #[serde(default, rename = "triangle")]
+    triangles: Vec<TriangleDesc>,
+    #[serde(default, rename = "disk")]
+    disks: Vec<DiskDesc>,
+    #[serde(default, rename = "cylinder")]
+    cylinders: Vec<CylinderDesc>,
+    #[serde(default, rename = "mesh")]
+    meshes: Vec<MeshDesc>,
+    // emissive spheres and rects are added as area lights automatically
+    #[serde(default, rename = "light")]
+    lights: Vec<LightDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RenderDesc {
+    #[serde(deserialize_with = "positive")]
+    width: usize,
+    #[serde(deserialize_with = "positive")]
+    height: usize,
+    #[serde(deserialize_with = "positive")]
+    samples: u32,
+    max_depth: u32,
+    seed: u64,
+    sampler: SamplerKind,
+    // relative error at which pixels stop sampling, off when missing
+    #[serde(deserialize_with = "positive_option")]
+    adaptive_threshold: Option<f32>,
+    #[serde(deserialize_with = "positive")]
+    tile_size: usize,
+    tile_order: TileOrder,
+    filter: FilterKind,
+    // the filter's own radius when missing
+    #[serde(deserialize_with = "positive_option")]
+    filter_radius: Option<f32>,
+    spectral: bool,
+}
+
+impl Default for RenderDesc {
+    fn default() -> RenderDesc {
+        let settings = Settings::default();
+        RenderDesc {
+            width: settings.width,
+            height: settings.height,
+            samples: settings.num_samples,
+            max_depth: settings.max_depth,
+            seed: settings.seed,
+            sampler: settings.sampler,
+            adaptive_threshold: settings.adaptive_threshold,
+            tile_size: settings.tile_size,
+            tile_order: settings.tile_order,
+            filter: settings.filter.kind,
+            filter_radius: None,
+            spectral: settings.spectral,
+        }
+    }
+}
+
+// a medium filling the space between all the surfaces
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FogDesc {
+    #[serde(deserialize_with = "positive")]
+    density: f32,
+    #[serde(default = "Vec3::ones")]
+    albedo: Vec3,
+    #[serde(default, deserialize_with = "asymmetry")]
+    g: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ColorDesc {
+    exposure: f32,
+    tone_map: ToneMap,
+    transfer: Transfer,
+    dither: bool,
+}
+
+impl Default for ColorDesc {
+    fn default() -> ColorDesc {
+        let color = ColorSettings::default();
+        ColorDesc {
+            exposure: color.exposure,
+            tone_map: color.tone_map,
+            transfer: color.transfer,
+            dither: color.dither,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraFields {
+    look_from: Vec3,
+    look_to: Vec3,
+    #[serde(default = "default_vup")]
+    vup: Vec3,
+    // top to bottom in degrees
+    #[serde(deserialize_with = "positive")]
+    vfov: f32,
+    #[serde(default)]
+    aperture: f32,
+    focus_dist: Option<f32>,
+    // moving spheres blur over the time between these, see `SphereDesc::center1`
+    #[serde(default)]
+    shutter_open: f32,
+    #[serde(default)]
+    shutter_close: f32,
+}
+
+fn default_vup() -> Vec3 {
+    Vec3::new(0.0, 1.0, 0.0)
+}
+
+// checked to look somewhere with a way up when parsed
+#[derive(Deserialize)]
+#[serde(try_from = "CameraFields")]
+struct CameraDesc(CameraSettings);
+
+impl TryFrom<CameraFields> for CameraDesc {
+    type Error = String;
+
+    fn try_from(c: CameraFields) -> Result<CameraDesc, String> {
+        let camera = CameraSettings {
+            look_from: c.look_from,
+            look_to: c.look_to,
+            vup: c.vup,
+            vfov: c.vfov,
+            aperture: c.aperture,
+            focus_dist: c.focus_dist,
+            shutter_open: c.shutter_open,
+            shutter_close: c.shutter_close,
+        };
+        camera.validate()?;
+        Ok(CameraDesc(camera))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum BackgroundDesc {
+    Gradient { bottom: Vec3, top: Vec3 },
+    Solid { color: Vec3 },
+    // equirectangular, radiance hdr, openexr or an srgb image, lights the scene as well
+    Environment {
+        file: PathBuf,
+        // degrees counterclockwise about the y axis
+        #[serde(default)]
+        rotation: f32,
+        #[serde(default = "default_intensity", deserialize_with = "non_negative")]
+        intensity: f32,
+    },
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereDesc {
+    // at time 0
+    center: Vec3,
+    // at time 1, for a sphere moving in a straight line
+    center1: Option<Vec3>,
+    #[serde(deserialize_with = "positive")]
+    radius: f32,
+    material: MaterialDesc,
+    #[serde(default)]
+    transform: Vec<TransformDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlaneDesc {
+    point: Vec3,
+    normal: Vec3,
+    material: SurfaceMaterialDesc,
+    #[serde(default)]
+    transform: Vec<TransformDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RectCorners {
+    min: Vec3,
+    max: Vec3,
+    material: SurfaceMaterialDesc,
+    #[serde(default)]
+    transform: Vec<TransformDesc>,
+}
+
+// corners are checked to be axis aligned when parsed
+#[derive(Deserialize)]
+#[serde(try_from = "RectCorners")]
+struct RectDesc(RectCorners);
+
+impl TryFrom<RectCorners> for RectDesc {
+    type Error = String;
+
+    fn try_from(c: RectCorners) -> Result<RectDesc, String> {
+        if (0..3).any(|a| c.min[a] == c.max[a]) {
+            Ok(RectDesc(c))
+        } else {
+            Err("rect corners must share an x, y or z coordinate".to_string())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoxDesc {
+    min: Vec3,
+    max: Vec3,
+    material: MaterialDesc,
+    #[serde(default)]
+    transform: Vec<TransformDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriangleDesc {
+    vertices: [Vec3; 3],
+    material: SurfaceMaterialDesc,
+    #[serde(default)]
+    transform: Vec<TransformDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DiskDesc {
+    center: Vec3,
+    normal: Vec3,
+    #[serde(deserialize_with = "positive")]
+    radius: f32,
+    material: SurfaceMaterialDesc,
+    #[serde(default)]
+    transform: Vec<TransformDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CylinderDesc {
+    base: Vec3,
+    #[serde(deserialize_with = "positive")]
+    radius: f32,
+    #[serde(deserialize_with = "positive")]
+    height: f32,
+    material: MaterialDesc,
+    #[serde(default)]
+    transform: Vec<TransformDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeshDesc {
+    // obj or ply
+    file: PathBuf,
+    material: MaterialDesc,
+    #[serde(default)]
+    transform: Vec<TransformDesc>,
+    // copies sharing the one mesh, each placed by its own transform after the
+    // mesh's, instead of the mesh itself
+    #[serde(default, rename = "instance")]
+    instances: Vec<InstanceDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InstanceDesc {
+    transform: Vec<TransformDesc>,
+}
+
+// one step of a transform, a list of steps applies in order
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+enum TransformDesc {
+    Translate(Vec3),
+    // counterclockwise in degrees looking down the axis
+    Rotate { #[serde(deserialize_with = "axis")] axis: Vec3, angle: f32 },
+    Scale(ScaleDesc),
+}
+
+// a zero axis would turn the whole transform into NaNs
+fn axis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec3, D::Error> {
+    let axis = Vec3::deserialize(deserializer)?;
+    if axis.length() > 0.0 {
+        Ok(axis)
+    } else {
+        Err(de::Error::custom("rotation axis must not be zero"))
+    }
+}
+
+// a single factor for all axes or one per axis, checked to be non-zero when parsed
+#[derive(Deserialize)]
+#[serde(try_from = "ScaleFactors")]
+struct ScaleDesc(Vec3);
+
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "a scale factor or factors [x, y, z]")]
+enum ScaleFactors {
+    Uniform(f32),
+    PerAxis(Vec3),
+}
+
+impl TryFrom<ScaleFactors> for ScaleDesc {
+    type Error = String;
+
+    fn try_from(factors: ScaleFactors) -> Result<ScaleDesc, String> {
+        let v = match factors {
+            ScaleFactors::Uniform(s) => Vec3::new(s, s, s),
+            ScaleFactors::PerAxis(v) => v,
+        };
+        if v.x == 0.0 || v.y == 0.0 || v.z == 0.0 {
+            Err("scale factors must not be zero".to_string())
+        } else {
+            Ok(ScaleDesc(v))
+        }
+    }
+}
+
+fn build_transform(steps: &[TransformDesc]) -> Transform {
+    steps.iter().fold(Transform::identity(), |t, step| {
+        let next = match *step {
+            TransformDesc::Translate(offset) => Transform::translate(offset),
+            TransformDesc::Rotate { axis, angle } => Transform::rotate(axis, angle),
+            TransformDesc::Scale(ScaleDesc(factors)) => Transform::scale(factors),
+        };
+        next * t
+    })
+}
+
+// objects without transform steps are left as they are
+fn place(object: Box<dyn Hitable>, steps: &[TransformDesc]) -> Box<dyn Hitable> {
+    if steps.is_empty() {
+        object
+    } else {
+        Box::new(Instance::new(Arc::from(object), build_transform(steps)))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum LightDesc {
+    Point { position: Vec3, intensity: Vec3 },
+    Directional { direction: Vec3, irradiance: Vec3 },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian { albedo: TextureDesc },
+    Metal { albedo: TextureDesc, #[serde(default)] fuzz: f32 },
+    Dielectric {
+        ref_idx: IorDesc,
+        // per unit of distance inside, clear glass when missing
+        #[serde(default = "Vec3::zeros", deserialize_with = "non_negative_color")]
+        absorption: Vec3,
+    },
+    DiffuseLight { emit: Vec3 },
+    // roughness and metallic between 0 and 1, as in most pbr workflows
+    Microfacet {
+        albedo: TextureDesc,
+        #[serde(default = "default_roughness", deserialize_with = "unit_interval")]
+        roughness: f32,
+        // 1 with a conductor, 0 without
+        #[serde(default, deserialize_with = "unit_interval_option")]
+        metallic: Option<f32>,
+        // the complex index of refraction of the metal, the albedo stays the color
+        // of the non-metallic part
+        conductor: Option<ConductorDesc>,
+    },
+    // fills a closed shape with a participating medium instead of covering it
+    Volume {
+        #[serde(deserialize_with = "positive")]
+        density: f32,
+        #[serde(default = "default_volume_albedo")]
+        albedo: TextureDesc,
+        #[serde(default, deserialize_with = "asymmetry")]
+        g: f32,
+    },
+}
+
+// a plain index of refraction, or the coefficients of a dispersion formula with the
+// wavelength in micrometres, checked to give a positive index when parsed
+#[derive(Deserialize)]
+#[serde(try_from = "IorFormula")]
+struct IorDesc(Ior);
+
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "an index of refraction or a table of cauchy (a, b) or sellmeier (b, c) coefficients")]
+enum IorFormula {
+    Constant(f32),
+    Cauchy(CauchyDesc),
+    Sellmeier(SellmeierDesc),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CauchyDesc {
+    a: f32,
+    b: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SellmeierDesc {
+    b: [f32; 3],
+    c: [f32; 3],
+}
+
+impl TryFrom<IorFormula> for IorDesc {
+    type Error = String;
+
+    fn try_from(formula: IorFormula) -> Result<IorDesc, String> {
+        let ior = match formula {
+            IorFormula::Constant(n) => Ior::Constant(n),
+            IorFormula::Cauchy(CauchyDesc { a, b }) => Ior::Cauchy { a, b },
+            IorFormula::Sellmeier(SellmeierDesc { b, c }) => Ior::Sellmeier { b, c },
+        };
+        // a resonance inside the visible range sends the index to infinity there
+        if let Ior::Sellmeier { c, .. } = ior {
+            let (min, max) = (WAVELENGTH_MIN * 1e-3, WAVELENGTH_MAX * 1e-3);
+            if c.iter().any(|&c| c >= min * min && c <= max * max) {
+                return Err("sellmeier c terms must not put a resonance inside the visible range".to_string());
+            }
+        }
+        // every nanometre of it, and where rgb rendering looks
+        let wavelengths = (WAVELENGTH_MIN as u32..=WAVELENGTH_MAX as u32).map(|l| l as f32).chain(Some(WAVELENGTH_D));
+        if wavelengths.map(|l| ior.at(Some(l))).all(|n| n > 0.0) {
+            Ok(IorDesc(ior))
+        } else {
+            Err("index of refraction must be positive over the visible range".to_string())
+        }
+    }
+}
+
+fn default_roughness() -> f32 {
+    0.5
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConductorDesc {
+    eta: Vec3,
+    k: Vec3,
+}
+
+fn default_volume_albedo() -> TextureDesc {
+    TextureDesc::Constant(Vec3::ones())
+}
+
+// of the henyey-greenstein phase function, which turns into a delta at -1 and 1
+fn asymmetry<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let g = f32::deserialize(deserializer)?;
+    if g > -1.0 && g < 1.0 {
+        Ok(g)
+    } else {
+        Err(de::Error::custom(format!("expected a value between -1 and 1 exclusive, found {}", g)))
+    }
+}
+
+// any material but a volume, which needs a closed shape to fill, checked when parsed
+#[derive(Deserialize)]
+#[serde(try_from = "MaterialDesc")]
+struct SurfaceMaterialDesc(MaterialDesc);
+
+impl TryFrom<MaterialDesc> for SurfaceMaterialDesc {
+    type Error = String;
+
+    fn try_from(material: MaterialDesc) -> Result<SurfaceMaterialDesc, String> {
+        match material {
+            MaterialDesc::Volume { .. } => Err("only a sphere, box, cylinder or mesh can enclose a volume".to_string()),
+            _ => Ok(SurfaceMaterialDesc(material)),
+        }
+    }
+}
+
+impl MaterialDesc {
+    fn build(&self, base_dir: &Path) -> Result<Arc<dyn Material>, SceneError> {
+        Ok(match self {
+            MaterialDesc::Lambertian { albedo } => Arc::new(Lambertian::new(albedo.build(base_dir)?)),
+            MaterialDesc::Metal { albedo, fuzz } => Arc::new(Metal::new(albedo.build(base_dir)?, *fuzz)),
+            MaterialDesc::Dielectric { ref_idx: IorDesc(ior), absorption } => {
+                Arc::new(Dielectric { absorption: *absorption, ..Dielectric::with_ior(*ior) })
+            }
+            MaterialDesc::DiffuseLight { emit } => Arc::new(DiffuseLight::new(*emit)),
+            MaterialDesc::Microfacet { albedo, roughness, metallic, conductor } => {
+                let fresnel = conductor.as_ref().map_or(Fresnel::Schlick, |c| Fresnel::Conductor { eta: c.eta, k: c.k });
+                let metallic = metallic.unwrap_or(if conductor.is_some() { 1.0 } else { 0.0 });
+                Arc::new(Microfacet::new(albedo.build(base_dir)?, *roughness, metallic, fresnel))
+            }
+            MaterialDesc::Volume { albedo, g, .. } => Arc::new(Volume::new(albedo.build(base_dir)?, *g)),
+        })
+    }
+
+    // emissive shapes that can be sampled are added as area lights
+    fn is_light(&self) -> bool {
+        matches!(self, MaterialDesc::DiffuseLight { .. })
+    }
+
+    // wraps closed shapes with a volume material in the medium they enclose
+    fn fill(&self, object: Box<dyn Hitable>) -> Box<dyn Hitable> {
+        match self {
+            MaterialDesc::Volume { density, .. } => Box::new(ConstantMedium::new(object, *density)),
+            _ => object,
+        }
+    }
+}
+
+// either a plain color or a table with a texture type
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "a color [r, g, b] or a texture table")]
+enum TextureDesc {
+    Constant(Vec3),
+    Pattern(PatternDesc),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum PatternDesc {
+    Checker {
+        odd: Box<TextureDesc>,
+        even: Box<TextureDesc>,
+        #[serde(default = "default_size", deserialize_with = "positive")]
+        size: f32,
+    },
+    // png or jpeg
+    Image { file: PathBuf },
+    Noise {
+        #[serde(default)]
+        style: NoiseStyle,
+        #[serde(default = "default_noise_color")]
+        color: Vec3,
+        #[serde(default = "default_size", deserialize_with = "positive")]
+        scale: f32,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+#[derive(Deserialize, Default, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+enum NoiseStyle {
+    #[default]
+    Smooth,
+    Turbulence,
+    Marble,
+}
+
+fn default_size() -> f32 {
+    1.0
+}
+
+fn default_noise_color() -> Vec3 {
+    Vec3::ones()
+}
+
+fn default_octaves() -> u32 {
+    7
+}
+
+impl TextureDesc {
+    fn build(&self, base_dir: &Path) -> Result<Texture, SceneError> {
+        Ok(match self {
+            TextureDesc::Constant(color) => Texture::Constant(*color),
+            TextureDesc::Pattern(PatternDesc::Checker { odd, even, size }) => {
+                Texture::checker(odd.build(base_dir)?, even.build(base_dir)?, *size)
+            }
+            TextureDesc::Pattern(PatternDesc::Image { file }) => {
+                let path = base_dir.join(file);
+                let image = Image::load(&path).map_err(|e| SceneError::Texture(path, e))?;
+                Texture::Image(Arc::new(image))
+            }
+            TextureDesc::Pattern(PatternDesc::Noise { style, color, scale, octaves, seed }) => {
+                let kind = match style {
+                    NoiseStyle::Smooth => NoiseKind::Smooth,
+                    NoiseStyle::Turbulence => NoiseKind::Turbulence,
+                    NoiseStyle::Marble => NoiseKind::Marble,
+                };
+                let perlin = Perlin::new(&mut StdRng::seed_from_u64(*seed));
+                Texture::Noise(Noise { perlin: Arc::new(perlin), kind, color: *color, scale: *scale, octaves: *octaves })
+            }
+        })
+    }
+}
+
+impl SceneDesc {
+    fn build(self, base_dir: &Path) -> Result<Scene, SceneError> {
+        let settings = Settings {
+            width: self.render.width,
+            height: self.render.height,
+            num_samples: self.render.samples,
+            max_depth: self.render.max_depth,
+            seed: self.render.seed,
+            sampler: self.render.sampler,
+            adaptive_threshold: self.render.adaptive_threshold,
+            tile_size: self.render.tile_size,
+            tile_order: self.render.tile_order,
+            filter: Filter {
+                kind: self.render.filter,
+                radius: self.render.filter_radius.unwrap_or_else(|| self.render.filter.default_radius()),
+            },
+            spectral: self.render.spectral,
+        };
+        let CameraDesc(camera) = self.camera;
+        let mut objects: Vec<Box<dyn Hitable>> = Vec::new();
+        let mut lights = Vec::new();
+        for s in &self.spheres {
+            let material = s.material.build(base_dir)?;
+            let sphere = match s.center1 {
+                Some(center1) => Sphere::moving(s.center, center1, s.radius, material),
+                None => Sphere::new(s.center, s.radius, material),
+            };
+            // light sampling assumes the light keeps still where it was described,
+            // moving and transformed ones are only found by the paths that hit them
+            if s.material.is_light() && s.center1.is_none() && s.transform.is_empty() {
+                lights.push(Light::Area(Box::new(sphere.clone())));
+            }
+            objects.push(s.material.fill(place(Box::new(sphere), &s.transform)));
+        }
+        for p in &self.planes {
+            let plane = Plane::new(p.point, p.normal, p.material.0.build(base_dir)?);
+            objects.push(place(Box::new(plane), &p.transform));
+        }
+        for RectDesc(r) in &self.rects {
+            let rect = Rect::new(r.min, r.max, r.material.0.build(base_dir)?).expect("rect corners are checked when parsed");
+            if r.material.0.is_light() && r.transform.is_empty() {
+                lights.push(Light::Area(Box::new(rect.clone())));
+            }
+            objects.push(place(Box::new(rect), &r.transform));
+        }
+        for b in &self.boxes {
+            let cuboid = Cuboid::new(b.min, b.max, b.material.build(base_dir)?);
+            objects.push(b.material.fill(place(Box::new(cuboid), &b.transform)));
+        }
+        for t in &self.triangles {
+            let [v0, v1, v2] = t.vertices;
+            let triangle = Triangle::new(v0, v1, v2, t.material.0.build(base_dir)?);
+            objects.push(place(Box::new(triangle), &t.transform));
+        }
+        for d in &self.disks {
+            let disk = Disk::new(d.center, d.normal, d.radius, d.material.0.build(base_dir)?);
+            objects.push(place(Box::new(disk), &d.transform));
+        }
+        for c in &self.cylinders {
+            let cylinder = Cylinder::new(c.base, c.radius, c.height, c.material.build(base_dir)?);
+            objects.push(c.material.fill(place(Box::new(cylinder), &c.transform)));
+        }
+        for m in &self.meshes {
+            let path = base_dir.join(&m.file);
+            let mesh = Mesh::load(&path, m.material.build(base_dir)?).map_err(|e| SceneError::Mesh(path, e))?;
+            if m.instances.is_empty() {
+                objects.push(m.material.fill(place(Box::new(mesh), &m.transform)));
+                continue;
+            }
+            let mesh: Arc<dyn Hitable> = Arc::new(mesh);
+            let transform = build_transform(&m.transform);
+            for i in &m.instances {
+                let instance = Instance::new(mesh.clone(), build_transform(&i.transform) * transform);
+                objects.push(m.material.fill(Box::new(instance)));
+            }
+        }
+        let background = match self.background {
+            Some(BackgroundDesc::Gradient { bottom, top }) => Background::Gradient { bottom, top },
+            Some(BackgroundDesc::Solid { color }) => Background::Solid(color),
+            Some(BackgroundDesc::Environment { ref file, rotation, intensity }) => {
+                let path = base_dir.join(file);
+                let map = EnvironmentMap::load(&path, rotation, intensity).map_err(|e| SceneError::Texture(path, e))?;
+                let map = Arc::new(map);
+                lights.push(Light::Environment(map.clone()));
+                Background::Environment(map)
+            }
+            None => Background::sky(),
+        };
+        for l in &self.lights {
+            lights.push(match *l {
+                LightDesc::Point { position, intensity } => Light::Point { position, intensity },
+                LightDesc::Directional { direction, irradiance } => {
+                    Light::Directional { direction: direction.make_unit_vector(), irradiance }
+                }
+            });
+        }
+        let color = ColorSettings {
+            exposure: self.color.exposure,
+            tone_map: self.color.tone_map,
+            transfer: self.color.transfer,
+            dither: self.color.dither,
+        };
+        let fog = self.fog.map(|f| Fog::new(f.density, Volume::new(f.albedo, f.g)));
+        let media = self.spheres.iter().map(|s| &s.material)
+            .chain(self.boxes.iter().map(|b| &b.material))
+            .chain(self.cylinders.iter().map(|c| &c.material))
+            .chain(self.meshes.iter().map(|m| &m.material))
+            .any(|m| matches!(m, MaterialDesc::Volume { .. }));
+        Ok(Scene { settings, camera, world: World::new(objects), background, lights, color, fog, media })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = "[camera]\nlook_from = [0.0, 1.0, 5.0]\nlook_to = [0.0, 0.0, 0.0]\nvfov = 40.0\n";
+
+    fn parse(body: &str) -> Result<Scene, SceneError> {
+        Scene::from_toml(&format!("{}{}", CAMERA, body), Path::new(""))
+    }
+
+    fn error(body: &str) -> String {
+        match parse(body) {
+            Ok(_) => panic!("expected {:?} to be rejected", body),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn defaults_fill_in_missing_sections() {
+        let scene = parse("").unwrap();
+        let defaults = Settings::default();
+        assert_eq!((scene.settings.width, scene.settings.height), (defaults.width, defaults.height));
+        assert_eq!(scene.settings.num_samples, defaults.num_samples);
+        assert!(scene.lights.is_empty());
+        assert!(scene.fog.is_none());
+    }
+
+    #[test]
+    fn emissive_shapes_become_lights() {
+        let scene = parse("[[sphere]]\ncenter = [0.0, 2.0, 0.0]\nradius = 0.5\n\
+                           material = { type = \"diffuse_light\", emit = [4.0, 4.0, 4.0] }\n").unwrap();
+        assert_eq!(scene.lights.len(), 1);
+    }
+
+    #[test]
+    fn camera_is_required() {
+        let e = Scene::from_toml("", Path::new("")).err().unwrap();
+        assert!(e.to_string().contains("missing field `camera`"), "{}", e);
+    }
+
+    #[test]
+    fn degenerate_cameras_are_rejected() {
+        let camera = |look_to: &str, vup: &str| {
+            format!("[camera]\nlook_from = [0.0, 1.0, 5.0]\nlook_to = {}\nvup = {}\nvfov = 40.0\n", look_to, vup)
+        };
+        let e = Scene::from_toml(&camera("[0.0, 1.0, 5.0]", "[0.0, 1.0, 0.0]"), Path::new("")).err().unwrap().to_string();
+        assert!(e.contains("camera look_from and look_to must differ") && e.contains("line 1"), "{}", e);
+        let e = Scene::from_toml(&camera("[0.0, 3.0, 5.0]", "[0.0, 1.0, 0.0]"), Path::new("")).err().unwrap().to_string();
+        assert!(e.contains("camera vup must not be parallel to the view direction"), "{}", e);
+        assert!(Scene::from_toml(&camera("[0.0, 3.0, 5.0]", "[0.0, 0.0, 1.0]"), Path::new("")).is_ok());
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert!(error("[render]\nwidht = 100\n").contains("unknown field `widht`"));
+        assert!(error("[[sphere]]\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\n\
+                       material = { type = \"plastic\" }\n").contains("unknown variant `plastic`"));
+    }
+
+    #[test]
+    fn values_are_range_checked() {
+        assert!(error("[render]\nwidth = 0\n").contains("expected a positive value, found 0"));
+        assert!(error("[render]\nsamples = 0\n").contains("expected a positive value"));
+        assert!(error("[render]\nfilter_radius = -1.0\n").contains("expected a positive value, found -1"));
+        assert!(error("[render]\nfilter_radius = nan\n").contains("expected a positive value, found NaN"));
+        assert!(error("[[sphere]]\ncenter = [0.0, 0.0, 0.0]\nradius = -1.0\n\
+                       material = { type = \"lambertian\", albedo = [0.5, 0.5, 0.5] }\n")
+                .contains("expected a positive value, found -1"));
+    }
+
+    #[test]
+    fn microfacet_parameters_are_checked() {
+        let sphere = |params: &str| format!("[[sphere]]\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\n\
+                                             material = {{ type = \"microfacet\", albedo = [0.5, 0.5, 0.5]{} }}\n", params);
+        assert!(parse(&sphere(", roughness = 1.0, metallic = 0.0")).is_ok());
+        assert!(error(&sphere(", roughness = 1.5")).contains("expected a value between 0 and 1, found 1.5"));
+        assert!(error(&sphere(", metallic = -0.5")).contains("expected a value between 0 and 1, found -0.5"));
+        assert!(error(&sphere(", roughness = nan")).contains("expected a value between 0 and 1, found NaN"));
+    }
+
+    #[test]
+    fn dispersion_formulas_are_checked() {
+        let sphere = |ref_idx: &str| format!("[[sphere]]\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\n\
+                                              material = {{ type = \"dielectric\", ref_idx = {} }}\n", ref_idx);
+        assert!(parse(&sphere("1.5")).is_ok());
+        assert!(parse(&sphere("{ a = 1.5, b = 0.004 }")).is_ok());
+        assert!(parse(&sphere("{ b = [1.04, 0.23, 1.01], c = [0.006, 0.02, 103.6] }")).is_ok());
+        assert!(error(&sphere("-1.5")).contains("index of refraction must be positive"));
+        // a resonance at 500 nm
+        assert!(error(&sphere("{ b = [0.01, 0.0, 0.0], c = [0.25, 0.0, 0.0] }"))
+                .contains("sellmeier c terms must not put a resonance inside the visible range"));
+    }
+
+    #[test]
+    fn absorption_is_non_negative() {
+        let e = error("[[sphere]]\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\n\
+                       material = { type = \"dielectric\", ref_idx = 1.5, absorption = [0.5, -0.1, 0.0] }\n");
+        assert!(e.contains("expected non-negative values, found [0.5, -0.1, 0]"), "{}", e);
+    }
+
+    #[test]
+    fn environment_intensity_is_non_negative() {
+        let e = error("[background]\ntype = \"environment\"\nfile = \"sky.exr\"\nintensity = -1.0\n");
+        assert!(e.contains("expected a non-negative value, found -1"), "{}", e);
+    }
+
+    #[test]
+    fn errors_report_the_line() {
+        let e = error("[render]\nwidth = \"wide\"\n");
+        assert!(e.contains("line 6"), "{}", e);
+    }
+
+    #[test]
+    fn shapes_are_validated() {
+        assert!(error("[[rect]]\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 1.0, 1.0]\n\
+                       material = { type = \"lambertian\", albedo = [0.5, 0.5, 0.5] }\n")
+                .contains("rect corners must share an x, y or z coordinate"));
+        assert!(error("[[box]]\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 1.0, 1.0]\n\
+                       material = { type = \"lambertian\", albedo = [0.5, 0.5, 0.5] }\n\
+                       transform = [{ scale = 0.0 }]\n")
+                .contains("scale factors must not be zero"));
+        assert!(error("[[box]]\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 1.0, 1.0]\n\
+                       material = { type = \"lambertian\", albedo = [0.5, 0.5, 0.5] }\n\
+                       transform = [{ rotate = { axis = [0.0, 0.0, 0.0], angle = 30.0 } }]\n")
+                .contains("rotation axis must not be zero"));
+        let e = error("[[plane]]\npoint = [0.0, 0.0, 0.0]\nnormal = [0.0, 1.0, 0.0]\n\
+                       material = { type = \"volume\", density = 1.0 }\n");
+        assert!(e.contains("only a sphere, box, cylinder or mesh can enclose a volume") && e.contains("line 8"), "{}", e);
+        let e = error("[[sphere]]\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\n\
+                       material = { type = \"volume\", density = 1.0, g = 1.0 }\n");
+        assert!(e.contains("expected a value between -1 and 1 exclusive, found 1"), "{}", e);
+        assert!(error("[fog]\ndensity = 0.1\ng = -1.5\n").contains("found -1.5"));
+    }
+
+    #[test]
+    fn every_shape_takes_a_transform() {
+        let transform = "transform = [{ scale = [1.0, 2.0, 1.0] }, { translate = [0.0, 1.0, 0.0] }]\n";
+        let material = "material = { type = \"lambertian\", albedo = [0.5, 0.5, 0.5] }\n";
+        for shape in &["[[sphere]]\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\n",
+                       "[[plane]]\npoint = [0.0, 0.0, 0.0]\nnormal = [0.0, 1.0, 0.0]\n",
+                       "[[rect]]\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 0.0, 1.0]\n",
+                       "[[box]]\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 1.0, 1.0]\n",
+                       "[[triangle]]\nvertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]\n",
+                       "[[disk]]\ncenter = [0.0, 0.0, 0.0]\nnormal = [0.0, 1.0, 0.0]\nradius = 1.0\n",
+                       "[[cylinder]]\nbase = [0.0, 0.0, 0.0]\nradius = 1.0\nheight = 1.0\n"] {
+            let scene = parse(&format!("{}{}{}", shape, material, transform)).unwrap_or_else(|e| panic!("{}: {}", shape, e));
+            assert_eq!(scene.world.objects.len(), 1);
+        }
+        // transformed lights are not sampled where they were described
+        let scene = parse("[[rect]]\nmin = [0.0, 2.0, 0.0]\nmax = [1.0, 2.0, 1.0]\n\
+                           material = { type = \"diffuse_light\", emit = [4.0, 4.0, 4.0] }\n\
+                           transform = [{ translate = [0.0, 1.0, 0.0] }]\n").unwrap();
+        assert!(scene.lights.is_empty());
+    }
+
+    #[test]
+    fn missing_files_name_the_file() {
+        let e = error("[[mesh]]\nfile = \"missing.obj\"\n\
+                       material = { type = \"lambertian\", albedo = [0.5, 0.5, 0.5] }\n");
+        assert!(e.starts_with("could not load mesh missing.obj"), "{}", e);
+    }
+
+    #[test]
+    fn bundled_scenes_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "toml") {
+                if let Err(e) = Scene::load(&path) {
+                    panic!("{}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use std::sync::Arc;
+
+use crate::{
+    vec3::{Vec3},
+    ray::{Ray},
+    sampler::{Sampler},
+    material::{Material},
+    aabb::{Aabb},
+    hitable::{HitRecord, Hitable},
+};
+
+// padding so flat primitives still get a box with volume
+pub const THICKNESS: f32 = 0.0001;
+
+fn axis_vector(axis: usize) -> Vec3 {
+    match axis {
+        0 => Vec3::new(1.0, 0.0, 0.0),
+        1 => Vec3::new(0.0, 1.0, 0.0),
+        _ => Vec3::new(0.0, 0.0, 1.0),
+    }
+}
+
+// infinite plane through `point`
+#[derive(Clone)]
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: Arc<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: Arc<dyn Material>) -> Plane {
+        Plane { point, normal: normal.make_unit_vector(), material }
+    }
+}
+
+impl Hitable for Plane {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let denom = ray.direction.dot(self.normal);
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.point - ray.origin).dot(self.normal) / denom;
+        if !(t < t_max && t > t_min) {
+            return None;
+        }
+        // world units along two directions in the plane
+        let (a, b) = self.normal.basis();
+        let d = ray.point_at_parameter(t) - self.point;
+        Some(HitRecord::new(ray, t, self.normal, d.dot(a), d.dot(b), &*self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+// axis aligned rectangle, `min` and `max` share the coordinate of the normal axis
+#[derive(Clone)]
+pub struct Rect {
+    pub axis: usize,
+    pub min: Vec3,
+    pub max: Vec3,
+    pub material: Arc<dyn Material>,
+}
+
+impl Rect {
+    // None unless the corners lie in a plane perpendicular to an axis
+    pub fn new(p0: Vec3, p1: Vec3, material: Arc<dyn Material>) -> Option<Rect> {
+        let axis = (0..3).find(|&a| p0[a] == p1[a])?;
+        Some(Rect { axis, min: p0.min(p1), max: p0.max(p1), material })
+    }
+}
+
+impl Hitable for Rect {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let k = self.min[self.axis];
+        let t = (k - ray.origin[self.axis]) / ray.direction[self.axis];
+        if !(t < t_max && t > t_min) {
+            return None;
+        }
+        let p = ray.point_at_parameter(t);
+        let a = (self.axis + 1) % 3;
+        let b = (self.axis + 2) % 3;
+        if p[a] < self.min[a] || p[a] > self.max[a] || p[b] < self.min[b] || p[b] > self.max[b] {
+            return None;
+        }
+        let u = (p[a] - self.min[a]) / (self.max[a] - self.min[a]);
+        let v = (p[b] - self.min[b]) / (self.max[b] - self.min[b]);
+        Some(HitRecord::new(ray, t, axis_vector(self.axis), u, v, &*self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let pad = axis_vector(self.axis) * THICKNESS;
+        Some(Aabb::new(self.min - pad, self.max + pad))
+    }
+
+    // uniform over the area
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f32 {
+        match self.hit(Ray::new(origin, direction, 0.0), 0.0001, f32::MAX) {
+            Some(hit) => {
+                let distance_squared = hit.t * hit.t * direction.squared_length();
+                let cosine = (direction[self.axis] / direction.length()).abs();
+                let extent = self.max - self.min;
+                let area = extent[(self.axis + 1) % 3] * extent[(self.axis + 2) % 3];
+                distance_squared / (cosine * area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let extent = self.max - self.min;
+        let (r1, r2) = sampler.next_2d();
+        let (a, b) = ((self.axis + 1) % 3, (self.axis + 2) % 3);
+        let mut offset = [0.0f32; 3];
+        offset[a] = r1 * extent[a];
+        offset[b] = r2 * extent[b];
+        self.min + Vec3::from(offset) - origin
+    }
+}
+
+// axis aligned box
+#[derive(Clone)]
+pub struct Cuboid {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub material: Arc<dyn Material>,
+}
+
+impl Cuboid {
+    pub fn new(p0: Vec3, p1: Vec3, material: Arc<dyn Material>) -> Cuboid {
+        Cuboid { min: p0.min(p1), max: p0.max(p1), material }
+    }
+}
+
+impl Hitable for Cuboid {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (mut t_near, mut t_far) = (f32::NEG_INFINITY, f32::INFINITY);
+        let (mut near_axis, mut far_axis) = (0, 0);
+        for a in 0..3 {
+            let inv_d = 1.0 / ray.direction[a];
+            let mut t0 = (self.min[a] - ray.origin[a]) * inv_d;
+            let mut t1 = (self.max[a] - ray.origin[a]) * inv_d;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > t_near {
+                t_near = t0;
+                near_axis = a;
+            }
+            if t1 < t_far {
+                t_far = t1;
+                far_axis = a;
+            }
+        }
+        if t_near > t_far {
+            return None;
+        }
+        let (t, axis) = if t_near < t_max && t_near > t_min {
+            (t_near, near_axis)
+        } else if t_far < t_max && t_far > t_min {
+            (t_far, far_axis)
+        } else {
+            return None;
+        };
+        let p = ray.point_at_parameter(t);
+        let sign = if p[axis] > (self.min[axis] + self.max[axis]) * 0.5 { 1.0 } else { -1.0 };
+        // each face is mapped over the whole texture
+        let a = (axis + 1) % 3;
+        let b = (axis + 2) % 3;
+        let u = (p[a] - self.min[a]) / (self.max[a] - self.min[a]);
+        let v = (p[b] - self.min[b]) / (self.max[b] - self.min[b]);
+        Some(HitRecord::new(ray, t, axis_vector(axis) * sign, u, v, &*self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+}
+
+// the normal follows counter clockwise winding
+#[derive(Clone)]
+pub struct Triangle {
+    pub v0: Vec3,
+    pub v1: Vec3,
+    pub v2: Vec3,
+    pub material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3, material: Arc<dyn Material>) -> Triangle {
+        Triangle { v0, v1, v2, material }
+    }
+}
+
+impl Hitable for Triangle {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t, u, v) = intersect_triangle(ray, self.v0, self.v1, self.v2, t_min, t_max)?;
+        let normal = (self.v1 - self.v0).cross(self.v2 - self.v0).make_unit_vector();
+        Some(HitRecord::new(ray, t, normal, u, v, &*self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let pad = Vec3::new(THICKNESS, THICKNESS, THICKNESS);
+        Some(Aabb::new(self.v0.min(self.v1).min(self.v2) - pad,
+                       self.v0.max(self.v1).max(self.v2) + pad))
+    }
+}
+
+// moller-trumbore, returns t and the barycentric coordinates of v1 and v2
+pub fn intersect_triangle(ray: Ray, v0: Vec3, v1: Vec3, v2: Vec3, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let pvec = ray.direction.cross(edge2);
+    let det = edge1.dot(pvec);
+    if det.abs() < 1e-8 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = ray.origin - v0;
+    let u = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let qvec = tvec.cross(edge1);
+    let v = ray.direction.dot(qvec) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(qvec) * inv_det;
+    if t < t_max && t > t_min {
+        Some((t, u, v))
+    } else {
+        None
+    }
+}
+
+#[derive(Clone)]
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f32,
+    pub material: Arc<dyn Material>,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f32, material: Arc<dyn Material>) -> Disk {
+        Disk { center, normal: normal.make_unit_vector(), radius, material }
+    }
+}
+
+impl Hitable for Disk {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let denom = ray.direction.dot(self.normal);
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.center - ray.origin).dot(self.normal) / denom;
+        if !(t < t_max && t > t_min) {
+            return None;
+        }
+        if (ray.point_at_parameter(t) - self.center).squared_length() > self.radius * self.radius {
+            return None;
+        }
+        Some(HitRecord::new(ray, t, self.normal, 0.0, 0.0, &*self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let n = self.normal;
+        let extent = |c: f32| self.radius * (1.0 - c * c).max(0.0).sqrt() + THICKNESS;
+        let e = Vec3::new(extent(n.x), extent(n.y), extent(n.z));
+        Some(Aabb::new(self.center - e, self.center + e))
+    }
+}
+
+// capped cylinder standing on `base` along the y axis
+#[derive(Clone)]
+pub struct Cylinder {
+    pub base: Vec3,
+    pub radius: f32,
+    pub height: f32,
+    pub material: Arc<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, radius: f32, height: f32, material: Arc<dyn Material>) -> Cylinder {
+        Cylinder { base, radius, height, material }
+    }
+}
+
+impl Hitable for Cylinder {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let oc = ray.origin - self.base;
+        let d = ray.direction;
+        let mut closest_so_far = t_max;
+        let mut closest_normal: Option<Vec3> = None;
+        let mut consider = |t: f32, normal: Vec3| {
+            if t < closest_so_far && t > t_min {
+                closest_so_far = t;
+                closest_normal = Some(normal);
+            }
+        };
+
+        // side
+        let a = d.x * d.x + d.z * d.z;
+        let b = oc.x * d.x + oc.z * d.z;
+        let c = oc.x * oc.x + oc.z * oc.z - self.radius * self.radius;
+        let discriminant = b * b - a * c;
+        if a > 0.0 && discriminant > 0.0 {
+            let sqrt_d = discriminant.sqrt();
+            for &t in &[(-b - sqrt_d) / a, (-b + sqrt_d) / a] {
+                let y = oc.y + t * d.y;
+                if y >= 0.0 && y <= self.height {
+                    let p = oc + d * t;
+                    consider(t, Vec3::new(p.x, 0.0, p.z) / self.radius);
+                }
+            }
+        }
+
+        // caps
+        if d.y != 0.0 {
+            for &(y, normal) in &[(0.0, -1.0), (self.height, 1.0)] {
+                let t = (y - oc.y) / d.y;
+                let p = oc + d * t;
+                if p.x * p.x + p.z * p.z <= self.radius * self.radius {
+                    consider(t, Vec3::new(0.0, normal, 0.0));
+                }
+            }
+        }
+
+        closest_normal.map(|normal| HitRecord::new(ray, closest_so_far, normal, 0.0, 0.0, &*self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, 0.0, self.radius);
+        Some(Aabb::new(self.base - r, self.base + r + Vec3::new(0.0, self.height, 0.0)))
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{
+    vec3::{Vec3},
+};
+
+// visible range the wavelengths are sampled from, in nanometres
+pub const WAVELENGTH_MIN: f32 = 380.0;
+pub const WAVELENGTH_MAX: f32 = 780.0;
+
+// the sodium d line, where glasses quote their index of refraction
+pub const WAVELENGTH_D: f32 = 587.6;
+
+// the inverse of the mean of `weight(λ) weight(λ)ᵀ` over the visible range, turns
+// the weights into the spectra rgb colors are spread over
+const BASIS: [[f32; 3]; 3] = [
+    [7.398_077e-6, 8.942_281e-7, 3.378_204e-7],
+    [8.942_281e-7, 1.930_738e-5, 2.454_242e-6],
+    [3.378_204e-7, 2.454_242e-6, 1.609_329e-5],
+];
+
+// what a path knows of the wavelength it carries
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Wavelength {
+    // rgb rendering, dispersive materials use their index at the d line
+    Rgb,
+    // spectral rendering, but nothing on the path depended on the wavelength yet
+    Pending,
+    // in nanometres, picked by the first dispersive surface on the path
+    Sampled(f32),
+}
+
+impl Wavelength {
+    pub fn nanometres(self) -> Option<f32> {
+        match self {
+            Wavelength::Sampled(l) => Some(l),
+            _ => None,
+        }
+    }
+}
+
+// uniform over the visible range
+pub fn sample_wavelength(u: f32) -> f32 {
+    WAVELENGTH_MIN + (WAVELENGTH_MAX - WAVELENGTH_MIN) * u
+}
+
+fn lobe(x: f32, mean: f32, sigma_low: f32, sigma_high: f32) -> f32 {
+    let t = (x - mean) / if x < mean { sigma_low } else { sigma_high };
+    (-0.5 * t * t).exp()
+}
+
+// the cie 1931 2° colour matching functions, using the multi-lobe gaussian fit of
+// wyman, sloan and shirley
+pub fn cie_xyz(wavelength: f32) -> Vec3 {
+    let l = wavelength;
+    Vec3::new(
+        1.056 * lobe(l, 599.8, 37.9, 31.0) + 0.362 * lobe(l, 442.0, 16.0, 26.7) - 0.065 * lobe(l, 501.1, 20.4, 26.2),
+        0.821 * lobe(l, 568.8, 46.9, 40.5) + 0.286 * lobe(l, 530.9, 16.3, 31.1),
+        1.217 * lobe(l, 437.0, 11.8, 36.0) + 0.681 * lobe(l, 459.0, 26.0, 13.8),
+    )
+}
+
+pub fn xyz_to_linear_srgb(c: Vec3) -> Vec3 {
+    Vec3::new(
+        3.2406 * c.x - 1.5372 * c.y - 0.4986 * c.z,
+        -0.9689 * c.x + 1.8758 * c.y + 0.0415 * c.z,
+        0.0557 * c.x - 0.2040 * c.y + 1.0570 * c.z,
+    )
+}
+
+// what a unit of radiance at `wavelength` adds to the pixel, the color matching
+// functions in linear srgb over the density the wavelength was sampled with
+fn weight(wavelength: f32) -> Vec3 {
+    xyz_to_linear_srgb(cie_xyz(wavelength)) * (WAVELENGTH_MAX - WAVELENGTH_MIN)
+}
+
+// what rgb radiance carried by a path that only sees `wavelength` adds to the
+// pixel, the radiance is spread into a spectrum, read at the wavelength and turned
+// back into rgb, the colors of surfaces the path met before it picked the
+// wavelength stay rgb
+//
+// the spectra are a least squares fit that averages back to the same rgb over all
+// wavelengths, so light passing through dispersive glass keeps its color on
+// average while a saturated light only leaves the glass at its own wavelengths,
+// some wavelengths fall outside of the srgb gamut and the spectra dip below zero,
+// they cancel out as samples accumulate
+pub fn project(wavelength: f32, radiance: Vec3) -> Vec3 {
+    let w = weight(wavelength);
+    let basis = Vec3::new(
+        BASIS[0][0] * w.x + BASIS[0][1] * w.y + BASIS[0][2] * w.z,
+        BASIS[1][0] * w.x + BASIS[1][1] * w.y + BASIS[1][2] * w.z,
+        BASIS[2][0] * w.x + BASIS[2][1] * w.y + BASIS[2][2] * w.z,
+    );
+    w * basis.dot(radiance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the mean over evenly spaced wavelengths, what sampling them converges to
+    fn mean_projection(radiance: Vec3) -> Vec3 {
+        let n = 4000;
+        (0..n).fold(Vec3::zeros(), |sum, i| sum + project(sample_wavelength((i as f32 + 0.5) / n as f32), radiance)) / n as f32
+    }
+
+    #[test]
+    fn projections_average_to_the_same_color() {
+        for &c in &[Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.2, 0.5, 0.9)] {
+            let mean = mean_projection(c);
+            assert!((mean - c).length() < 1e-3, "{:?} averages to {:?}", c, mean);
+        }
+    }
+
+    #[test]
+    fn saturated_colors_stay_at_their_wavelengths() {
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        let blue = Vec3::new(0.0, 0.0, 1.0);
+        assert!(project(620.0, red).x > 5.0 * project(450.0, red).x.abs());
+        assert!(project(450.0, blue).z > 5.0 * project(620.0, blue).z.abs());
+    }
+
+    #[test]
+    fn luminance_peaks_in_the_green() {
+        let y = |l: f32| cie_xyz(l).y;
+        assert!(y(555.0) > 0.95 && y(555.0) < 1.05);
+        assert!(y(555.0) > y(450.0) && y(555.0) > y(650.0));
+    }
+}
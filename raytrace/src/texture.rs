@@ -0,0 +1,170 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use rand::prelude::*;
+
+use crate::{
+    vec3::{Vec3},
+    color::{srgb_to_linear},
+};
+
+#[derive(Clone)]
+pub enum Texture {
+    Constant(Vec3),
+    // alternates between `odd` and `even` in cubes of `size`
+    Checker { odd: Box<Texture>, even: Box<Texture>, size: f32 },
+    Image(Arc<Image>),
+    Noise(Noise),
+}
+
+impl Texture {
+    pub fn checker(odd: Texture, even: Texture, size: f32) -> Texture {
+        Texture::Checker { odd: Box::new(odd), even: Box::new(even), size }
+    }
+
+    pub fn value(&self, u: f32, v: f32, p: Vec3) -> Vec3 {
+        match self {
+            Texture::Constant(color) => *color,
+            Texture::Checker { odd, even, size } => {
+                let cell = (p.x / size).floor() + (p.y / size).floor() + (p.z / size).floor();
+                if cell.rem_euclid(2.0) < 1.0 { even.value(u, v, p) } else { odd.value(u, v, p) }
+            }
+            Texture::Image(image) => image.value(u, v),
+            Texture::Noise(noise) => noise.value(p),
+        }
+    }
+}
+
+impl From<Vec3> for Texture {
+    fn from(color: Vec3) -> Texture {
+        Texture::Constant(color)
+    }
+}
+
+// linear colors, top row first, repeats outside of [0, 1]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vec3>,
+}
+
+impl Image {
+    pub fn load(path: &Path) -> image::ImageResult<Image> {
+        let rgb = image::open(path)?.to_rgb8();
+        let (width, height) = (rgb.width() as usize, rgb.height() as usize);
+        let pixels = rgb.pixels()
+            .map(|p| {
+                let [r, g, b] = p.0.map(|c| srgb_to_linear(c as f32 / 255.0));
+                Vec3::new(r, g, b)
+            })
+            .collect();
+        Ok(Image { width, height, pixels })
+    }
+
+    pub fn value(&self, u: f32, v: f32) -> Vec3 {
+        if self.pixels.is_empty() {
+            return Vec3::new(0.0, 1.0, 1.0);
+        }
+        let i = ((u.rem_euclid(1.0) * self.width as f32) as usize).min(self.width - 1);
+        let j = (((1.0 - v.rem_euclid(1.0)) * self.height as f32) as usize).min(self.height - 1);
+        self.pixels[j * self.width + i]
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum NoiseKind {
+    // smooth perlin noise
+    Smooth,
+    // absolute sum of octaves of noise
+    Turbulence,
+    // stripes along z distorted by turbulence
+    Marble,
+}
+
+#[derive(Clone)]
+pub struct Noise {
+    pub perlin: Arc<Perlin>,
+    pub kind: NoiseKind,
+    pub color: Vec3,
+    // frequency of the pattern
+    pub scale: f32,
+    pub octaves: u32,
+}
+
+impl Noise {
+    pub fn value(&self, p: Vec3) -> Vec3 {
+        let intensity = match self.kind {
+            NoiseKind::Smooth => 0.5 * (1.0 + self.perlin.noise(p * self.scale)),
+            NoiseKind::Turbulence => self.perlin.turbulence(p * self.scale, self.octaves),
+            // only the stripes follow the scale, the veins keep their size
+            NoiseKind::Marble => 0.5 * (1.0 + (self.scale * p.z + 10.0 * self.perlin.turbulence(p, self.octaves)).sin()),
+        };
+        self.color * intensity
+    }
+}
+
+const POINT_COUNT: usize = 256;
+
+// gradient noise over a lattice of random unit vectors
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new<R: Rng>(rng: &mut R) -> Perlin {
+        let gradients = (0..POINT_COUNT)
+            .map(|_| {
+                let v = Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()) * 2.0 - Vec3::ones();
+                if v.squared_length() > 1e-6 { v.make_unit_vector() } else { Vec3::new(1.0, 0.0, 0.0) }
+            })
+            .collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(rng);
+            p
+        };
+        let (perm_x, perm_y, perm_z) = (permutation(), permutation(), permutation());
+        Perlin { gradients, perm_x, perm_y, perm_z }
+    }
+
+    // roughly in [-1, 1]
+    pub fn noise(&self, p: Vec3) -> f32 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+        // hermite smoothing of the interpolation weights
+        let (uu, vv, ww) = (u * u * (3.0 - 2.0 * u), v * v * (3.0 - 2.0 * v), w * w * (3.0 - 2.0 * w));
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize];
+                    let (a, b, c) = (di as f32, dj as f32, dk as f32);
+                    let weight = Vec3::new(u - a, v - b, w - c);
+                    accum += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * self.gradients[index].dot(weight);
+                }
+            }
+        }
+        accum
+    }
+
+    pub fn turbulence(&self, p: Vec3, octaves: u32) -> f32 {
+        let mut accum = 0.0;
+        let mut p = p;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            accum += weight * self.noise(p);
+            weight *= 0.5;
+            p = p * 2.0;
+        }
+        accum.abs()
+    }
+}
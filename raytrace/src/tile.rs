@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+use serde::{Deserialize};
+
+// the order tiles are handed out to the render threads
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TileOrder {
+    // rows from the top
+    Scanline,
+    // outwards from the center of the image
+    Spiral,
+    // along a hilbert curve, neighbouring tiles render close together in time
+    Hilbert,
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TileOrder, String> {
+        match s {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(format!("unknown tile order {}, expected scanline, spiral or hilbert", s)),
+        }
+    }
+}
+
+// a rectangle of pixels, `y` counts from the top row
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    // indices of the pixels in the image, row by row
+    pub fn pixels(&self, image_width: usize) -> impl Iterator<Item = usize> + '_ {
+        (self.y..self.y + self.height)
+            .flat_map(move |y| (self.x..self.x + self.width).map(move |x| y * image_width + x))
+    }
+}
+
+// covers the image with tiles of at most `size` pixels square
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+    let size = size.max(1);
+    let (nx, ny) = (width.div_ceil(size), height.div_ceil(size));
+    let cells: Vec<(usize, usize)> = match order {
+        TileOrder::Scanline => (0..ny).flat_map(|ty| (0..nx).map(move |tx| (tx, ty))).collect(),
+        TileOrder::Spiral => spiral(nx, ny),
+        TileOrder::Hilbert => {
+            let n = nx.max(ny).next_power_of_two();
+            let mut cells: Vec<_> = (0..ny).flat_map(|ty| (0..nx).map(move |tx| (tx, ty))).collect();
+            cells.sort_by_key(|&(tx, ty)| hilbert_index(n, tx, ty));
+            cells
+        }
+    };
+    cells.into_iter()
+        .map(|(tx, ty)| {
+            let (x, y) = (tx * size, ty * size);
+            Tile { x, y, width: size.min(width - x), height: size.min(height - y) }
+        })
+        .collect()
+}
+
+// walks a square spiral from the center tile, skipping steps outside the grid
+fn spiral(nx: usize, ny: usize) -> Vec<(usize, usize)> {
+    let mut cells = Vec::with_capacity(nx * ny);
+    let (mut x, mut y) = (((nx as i64) - 1) / 2, ((ny as i64) - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut step = 1;
+    let mut direction = 0;
+    while cells.len() < nx * ny {
+        // each step length is walked twice, turning after each
+        for _ in 0..2 {
+            let (dx, dy) = directions[direction % 4];
+            for _ in 0..step {
+                if x >= 0 && y >= 0 && (x as usize) < nx && (y as usize) < ny {
+                    cells.push((x as usize, y as usize));
+                }
+                x += dx;
+                y += dy;
+            }
+            direction += 1;
+        }
+        step += 1;
+    }
+    cells
+}
+
+// distance along the hilbert curve filling an `n` by `n` grid, `n` a power of two
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as usize;
+        let ry = (y & s > 0) as usize;
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_every_pixel_once() {
+        for &order in &[TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            for &(width, height, size) in &[(64, 64, 16), (37, 23, 8), (23, 37, 8), (100, 7, 32), (5, 5, 1), (3, 9, 16)] {
+                let mut covered = vec![0; width * height];
+                for tile in tiles(width, height, size, order) {
+                    assert!(tile.width <= size && tile.height <= size);
+                    for p in tile.pixels(width) {
+                        covered[p] += 1;
+                    }
+                }
+                assert!(covered.iter().all(|&n| n == 1), "{:?} tiles of {} on {}x{}", order, size, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn spiral_starts_at_the_center() {
+        let first = tiles(96, 64, 32, TileOrder::Spiral)[0];
+        assert_eq!((first.x, first.y), (32, 0));
+        assert_eq!(spiral(5, 3)[0], (2, 1));
+    }
+}
@@ -0,0 +1,210 @@
+use std::ops::{Mul};
+use std::sync::Arc;
+
+use crate::{
+    vec3::{Vec3},
+    ray::{Ray},
+    aabb::{Aabb},
+    hitable::{HitRecord, Hitable},
+};
+
+// rows of a 4x4 matrix whose last row is 0 0 0 1
+type Matrix = [[f32; 4]; 3];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum::<f32>();
+        }
+        row[3] += a[i][3];
+    }
+    m
+}
+
+// affine transform, kept together with its inverse
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform { m: IDENTITY, inv: IDENTITY }
+    }
+
+    pub fn translate(offset: Vec3) -> Transform {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for a in 0..3 {
+            m[a][3] = offset[a];
+            inv[a][3] = -offset[a];
+        }
+        Transform { m, inv }
+    }
+
+    // along each axis, none of the factors may be zero
+    pub fn scale(factors: Vec3) -> Transform {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for a in 0..3 {
+            m[a][a] = factors[a];
+            inv[a][a] = 1.0 / factors[a];
+        }
+        Transform { m, inv }
+    }
+
+    // counterclockwise in degrees looking down the axis
+    pub fn rotate(axis: Vec3, degrees: f32) -> Transform {
+        let a = axis.make_unit_vector();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let k = 1.0 - cos;
+        let m = [
+            [cos + a.x * a.x * k, a.x * a.y * k - a.z * sin, a.x * a.z * k + a.y * sin, 0.0],
+            [a.y * a.x * k + a.z * sin, cos + a.y * a.y * k, a.y * a.z * k - a.x * sin, 0.0],
+            [a.z * a.x * k - a.y * sin, a.z * a.y * k + a.x * sin, cos + a.z * a.z * k, 0.0],
+        ];
+        // rotations are orthogonal, the inverse is the transpose
+        let mut inv = IDENTITY;
+        for (i, row) in inv.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().take(3).enumerate() {
+                *value = m[j][i];
+            }
+        }
+        Transform { m, inv }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform { m: self.inv, inv: self.m }
+    }
+
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+                  m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+                  m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3])
+    }
+
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+                  m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+                  m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z)
+    }
+
+    // by the inverse transpose so normals stay perpendicular to the surface, not normalized
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        let inv = &self.inv;
+        Vec3::new(inv[0][0] * n.x + inv[1][0] * n.y + inv[2][0] * n.z,
+                  inv[0][1] * n.x + inv[1][1] * n.y + inv[2][1] * n.z,
+                  inv[0][2] * n.x + inv[1][2] * n.y + inv[2][2] * n.z)
+    }
+
+    // the box around the transformed corners of `b`
+    pub fn bounding_box(&self, b: Aabb) -> Aabb {
+        let corner = |i: usize| {
+            Vec3::new(if i & 1 == 0 { b.min.x } else { b.max.x },
+                      if i & 2 == 0 { b.min.y } else { b.max.y },
+                      if i & 4 == 0 { b.min.z } else { b.max.z })
+        };
+        let first = self.point(corner(0));
+        (1..8).map(|i| self.point(corner(i))).fold(Aabb::new(first, first), |acc, p| Aabb::new(acc.min.min(p), acc.max.max(p)))
+    }
+}
+
+// `a * b` applies `b` first
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, other: Transform) -> Transform {
+        Transform { m: multiply(&self.m, &other.m), inv: multiply(&other.inv, &self.inv) }
+    }
+}
+
+// places a possibly shared object in the world, rays are hit against it in its
+// own space
+pub struct Instance {
+    pub object: Arc<dyn Hitable>,
+    // from object to world space
+    pub transform: Transform,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hitable>, transform: Transform) -> Instance {
+        Instance { object, transform }
+    }
+}
+
+impl Hitable for Instance {
+    fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        // the direction is left unnormalized so t means the same in both spaces
+        let inverse = self.transform.inverse();
+        let local = Ray { origin: inverse.point(ray.origin), direction: inverse.vector(ray.direction), ..ray };
+        let mut hit = self.object.hit(local, t_min, t_max)?;
+        hit.p = self.transform.point(hit.p);
+        hit.normal = self.transform.normal(hit.normal).make_unit_vector();
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box().map(|b| self.transform.bounding_box(b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hitable::{Sphere}, material::{Lambertian}};
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    // a unit sphere stretched along x into an ellipsoid and moved back along z
+    fn ellipsoid() -> Instance {
+        let sphere = Sphere::new(Vec3::zeros(), 1.0, Arc::new(Lambertian::new(Vec3::ones())));
+        Instance::new(Arc::new(sphere), Transform::translate(Vec3::new(0.0, 0.0, -5.0)) * Transform::scale(Vec3::new(2.0, 1.0, 1.0)))
+    }
+
+    #[test]
+    fn hits_are_transformed_into_world_space() {
+        let ellipsoid = ellipsoid();
+        let ray = Ray::new(Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = ellipsoid.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-4);
+        assert!(close(hit.p, Vec3::new(0.0, 0.0, -4.0)));
+        assert!(close(hit.normal, Vec3::new(0.0, 0.0, 1.0)));
+        // past the stretched side, which the unit sphere would miss
+        let ray = Ray::new(Vec3::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(ellipsoid.hit(ray, 0.001, f32::MAX).is_some());
+    }
+
+    #[test]
+    fn normals_follow_the_inverse_transpose() {
+        // on x^2 / 4 + y^2 + z^2 = 1 the normal leans along (x / 4, y, z), not
+        // along the stretched normal of the sphere
+        let p = Vec3::new(2.0f32.sqrt(), 0.0, 0.5f32.sqrt());
+        let ray = Ray::new(Vec3::new(p.x, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let ellipsoid = ellipsoid();
+        let hit = ellipsoid.hit(ray, 0.001, f32::MAX).unwrap();
+        assert!(close(hit.p, p + Vec3::new(0.0, 0.0, -5.0)), "{:?}", hit.p);
+        assert!(close(hit.normal, Vec3::new(p.x / 4.0, 0.0, p.z).make_unit_vector()), "{:?}", hit.normal);
+    }
+
+    #[test]
+    fn bounding_boxes_cover_the_transformed_object() {
+        let b = ellipsoid().bounding_box().unwrap();
+        assert!(close(b.min, Vec3::new(-2.0, -1.0, -6.0)) && close(b.max, Vec3::new(2.0, 1.0, -4.0)), "{:?}", b);
+        // a quarter turn about z takes x to y and y to -x
+        let quarter = Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 90.0);
+        let b = quarter.bounding_box(Aabb::new(Vec3::zeros(), Vec3::new(1.0, 2.0, 3.0)));
+        assert!(close(b.min, Vec3::new(-2.0, 0.0, 0.0)) && close(b.max, Vec3::new(0.0, 1.0, 3.0)), "{:?}", b);
+    }
+}
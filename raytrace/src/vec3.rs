@@ -1,6 +1,9 @@
-use std::ops::{Neg, Add, Sub, Mul, Div};
+use std::ops::{Neg, Add, Sub, Mul, Div, Index};
+use serde::Deserialize;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+// deserializes from an `[x, y, z]` array
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(from = "[f32; 3]")]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -47,6 +50,41 @@ impl Vec3 {
     pub fn reflect(&self, n: Vec3) -> Vec3 {
         *self - n * 2.0 * self.dot(n)
     }
+
+    // two unit vectors completing an orthonormal basis with this unit vector
+    pub fn basis(&self) -> (Vec3, Vec3) {
+        let sign = 1.0f32.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+         Vec3::new(b, sign + self.y * self.y * a, -self.y))
+    }
+
+    pub fn min(&self, v: Vec3) -> Vec3 {
+        Vec3::new(self.x.min(v.x), self.y.min(v.y), self.z.min(v.z))
+    }
+    pub fn max(&self, v: Vec3) -> Vec3 {
+        Vec3::new(self.x.max(v.x), self.y.max(v.y), self.z.max(v.z))
+    }
+}
+
+impl Index<usize> for Vec3 {
+    type Output = f32;
+
+    fn index(&self, i: usize) -> &f32 {
+        match i {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 index out of range: {}", i),
+        }
+    }
+}
+
+impl From<[f32; 3]> for Vec3 {
+    fn from(v: [f32; 3]) -> Vec3 {
+        Vec3::new(v[0], v[1], v[2])
+    }
 }
 
 impl Neg for Vec3 {